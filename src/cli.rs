@@ -1,4 +1,14 @@
 pub mod cache;
+pub mod call;
+pub mod check;
+pub mod config;
+pub mod db;
+pub mod fmt;
+pub mod kv;
+pub mod new;
+pub mod repl;
+pub mod routes;
+pub mod test;
 pub mod verbose;
 
 pub fn get_version(short: bool) -> String {
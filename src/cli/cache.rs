@@ -5,7 +5,7 @@ use std::{fs, io, path::Path};
 
 use crate::{
     helpers::prelude::*,
-    routes::{self, parse, Route},
+    routes::{self, cache, parse, Route},
     structs::config::Config,
 };
 
@@ -75,7 +75,15 @@ fn is_dir_empty<P: AsRef<Path>>(path: P) -> io::Result<bool> {
     Ok(true)
 }
 
+fn require_disk_cache() {
+    if cache::is_memory() {
+        crashln!("{WARN} {}", "Route cache is kept in memory, nothing is stored on disk.")
+    }
+}
+
 pub fn clean(config: Config) {
+    require_disk_cache();
+
     // add error handling
     if is_dir_empty(&config.settings.cache).unwrap() {
         crashln!("{WARN} {}", "Route cache does not exist, cannot remove.")
@@ -89,6 +97,8 @@ pub fn clean(config: Config) {
 
 #[tokio_wrap::sync]
 pub fn build(config: Config) {
+    require_disk_cache();
+
     let contents = match get_workers(&config.workers).await {
         Ok(content) => content,
         Err(err) => crashln!("{FAIL} Failed to read contents, {err}"),
@@ -106,10 +116,12 @@ pub fn build(config: Config) {
 }
 
 #[tokio_wrap::sync]
-pub fn list(config: Config) {
+pub fn list() {
+    require_disk_cache();
+
     let mut internal_routes: Vec<Route> = Vec::new();
 
-    let index = match routes::routes_index(config.settings.cache).await {
+    let index = match routes::routes_index().await {
         Ok(index) => index.tap(|i| i.sort_by(|a, b| a.fn_name.cmp(&b.fn_name))),
         Err(err) => crashln!("{FAIL} Failed to read cache, {err}"),
     };
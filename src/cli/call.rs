@@ -0,0 +1,61 @@
+use colored::Colorize;
+use macros_rs::fmt::crashln;
+use std::{fs, sync::Arc};
+
+use crate::{
+    helpers::prelude::*,
+    http::{self, Failure, Request},
+    routes::cache,
+    structs::config::Config,
+};
+
+fn header(pair: &str) -> (String, String) {
+    match pair.split_once(['=', ':']) {
+        Some((name, value)) => (name.trim().to_lowercase(), value.trim().to_string()),
+        None => crashln!("{FAIL} Invalid header '{pair}', expected key=value"),
+    }
+}
+
+fn body(body: Option<String>) -> String {
+    match body {
+        Some(body) => match body.strip_prefix('@') {
+            Some(path) => fs::read_to_string(path).unwrap_or_else(|err| crashln!("{FAIL} Failed to read {path}, {err}")),
+            None => body,
+        },
+        None => String::new(),
+    }
+}
+
+/// Runs a route in-process and prints the response, without starting the server.
+#[tokio_wrap::sync]
+pub fn call(config: Config, method: String, url: String, headers: Vec<String>, data: Option<String>, raw: bool) {
+    let headers = headers.iter().map(|pair| header(pair)).collect();
+    let request = Request::synthetic(&method, &url, headers, body(data));
+
+    cache::use_memory();
+
+    let (body, content_type, status) = match http::invoke(Arc::new(config), request).await {
+        Ok(response) => response,
+        Err(Failure::NotFound(err)) => {
+            log::debug!(err, "Error finding route");
+            http::not_found(&url)
+        }
+        Err(Failure::Runtime(err)) => http::server_error(&err),
+    };
+
+    if !raw {
+        let line = format!("HTTP/1.1 {status}");
+        match status.is_success() {
+            true => println!("{}", line.green().bold()),
+            false => println!("{}", line.red().bold()),
+        }
+        println!("{} {content_type}", "content-type:".bold());
+        println!("{} {}\n", "content-length:".bold(), body.len());
+    }
+
+    println!("{body}");
+
+    if status.is_server_error() {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,55 @@
+use colored::Colorize;
+use macros_rs::fmt::crashln;
+use std::fs;
+
+use crate::{
+    helpers::prelude::*,
+    routes::lint::{self, Problem, Severity},
+    structs::config::Config,
+};
+
+fn print_problem(problem: &Problem) {
+    let location = format!("{}:{}", problem.file.display(), problem.line).white();
+
+    match problem.severity {
+        Severity::Error => println!("{} {location} {}", "error".red().bold(), problem.message),
+        Severity::Warning => println!("{} {location} {}", "warning".yellow().bold(), problem.message),
+    }
+}
+
+pub fn check(config: Config, strict: bool) {
+    let mut sources = Vec::new();
+    let mut problems = Vec::new();
+
+    for worker in &config.workers {
+        let contents = match fs::read_to_string(worker) {
+            Ok(contents) => contents,
+            Err(err) => {
+                problems.push(Problem {
+                    line: 0,
+                    file: worker.to_owned(),
+                    severity: Severity::Error,
+                    message: format!("cannot read worker, {err}"),
+                });
+                continue;
+            }
+        };
+
+        match lint::parse_file(worker.to_owned(), &contents) {
+            Ok(mut parsed) => sources.append(&mut parsed),
+            Err(problem) => problems.push(problem),
+        }
+    }
+
+    problems.append(&mut lint::lint(&sources));
+    problems.iter().for_each(print_problem);
+
+    let errors = problems.iter().filter(|p| p.severity == Severity::Error).count();
+    let warnings = problems.len() - errors;
+
+    if errors > 0 || (strict && warnings > 0) {
+        crashln!("{FAIL} Found {errors} error(s) and {warnings} warning(s) in {} route(s).", sources.len());
+    }
+
+    println!("{SUCCESS} Checked {} route(s), {warnings} warning(s).", sources.len());
+}
@@ -0,0 +1,45 @@
+use chrono::{DateTime, Local};
+use colored::Colorize;
+use macros_rs::fmt::crashln;
+
+use crate::{database::migrate, helpers::prelude::*, structs::config::Config};
+
+pub fn migrate(config: Config) {
+    let applied = migrate::migrate(&config, |migration| println!("{SUCCESS} Applied {}", migration.to_string().bold())).unwrap_or_else(|err| crashln!("{FAIL} {err}"));
+
+    if applied == 0 {
+        println!("{SUCCESS} Database is up to date");
+    }
+}
+
+pub fn rollback(config: Config, steps: usize) {
+    let reverted = migrate::rollback(&config, steps, |migration| println!("{SUCCESS} Rolled back {}", migration.to_string().bold())).unwrap_or_else(|err| crashln!("{FAIL} {err}"));
+
+    if reverted == 0 {
+        println!("{WARN} No migrations to roll back");
+    }
+}
+
+pub fn status(config: Config) {
+    let list = migrate::status(&config).unwrap_or_else(|err| crashln!("{FAIL} {err}"));
+
+    if list.is_empty() {
+        return println!("{WARN} No migrations found");
+    }
+
+    for status in &list {
+        let label = format!("{:04}_{}", status.version, status.name);
+
+        match (status.applied_at, status.exists) {
+            (Some(at), exists) => {
+                let at = DateTime::from_timestamp_millis(at).map(|at| at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string()).unwrap_or_default();
+                let missing = if exists { String::new() } else { format!(" {}", "(file missing)".red()) };
+                println!("{} {label} {}{missing}", "applied".green(), at.bright_black());
+            }
+            (None, _) => println!("{} {label}", "pending".yellow()),
+        }
+    }
+
+    let pending = list.iter().filter(|status| status.applied_at.is_none()).count();
+    println!("\n{STAR} {} applied, {pending} pending", list.len() - pending);
+}
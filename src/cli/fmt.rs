@@ -0,0 +1,55 @@
+use colored::Colorize;
+use macros_rs::fmt::crashln;
+use std::{fs, path::PathBuf};
+
+use crate::{helpers::prelude::*, routes::fmt, structs::config::Config};
+
+fn first_change(before: &str, after: &str) -> usize {
+    let mut lines = before.lines().zip(after.lines());
+    match lines.position(|(a, b)| a != b) {
+        Some(index) => index + 1,
+        None => before.lines().count().min(after.lines().count()) + 1,
+    }
+}
+
+/// Rewrites worker files in the canonical style, or reports them with `check`.
+pub fn format(config: Config, files: Vec<PathBuf>, check: bool) {
+    let files = match files.is_empty() {
+        true => config.workers,
+        false => files,
+    };
+
+    let mut changed = 0;
+
+    for file in &files {
+        let input = match fs::read_to_string(file) {
+            Ok(input) => input,
+            Err(err) => crashln!("{FAIL} Failed to read {}, {err}", file.display()),
+        };
+
+        let output = match fmt::format(&input) {
+            Ok(output) => output,
+            Err(err) => crashln!("{FAIL} Failed to parse {}\n{err}", file.display()),
+        };
+
+        if output == input {
+            continue;
+        }
+
+        changed += 1;
+
+        if check {
+            println!("{} {}:{} would be reformatted", "warning".yellow().bold(), file.display(), first_change(&input, &output));
+        } else if let Err(err) = fs::write(file, output) {
+            crashln!("{FAIL} Failed to write {}, {err}", file.display())
+        } else {
+            println!("{DASH} formatted {}", file.display());
+        }
+    }
+
+    match (changed, check) {
+        (0, _) => println!("{SUCCESS} {} file(s) already formatted", files.len()),
+        (_, true) => crashln!("{FAIL} {changed} of {} file(s) would be reformatted", files.len()),
+        (_, false) => println!("{SUCCESS} formatted {changed} of {} file(s)", files.len()),
+    }
+}
@@ -0,0 +1,114 @@
+use colored::Colorize;
+use macros_rs::fmt::crashln;
+use redb::Database;
+use serde_json::Value;
+
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    database::kv::{self, Entry},
+    helpers::prelude::*,
+};
+
+fn open(file: &str) -> Arc<Database> { kv::open(file.to_string()).unwrap_or_else(|err| crashln!("{FAIL} {err}")) }
+
+/// Values are read as json when they parse, otherwise as plain strings.
+fn parse_value(value: &str) -> Value { serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string())) }
+
+fn now() -> u64 { SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis() as u64).unwrap_or_default() }
+
+pub fn list(file: &str, prefix: Option<String>) {
+    let entries = kv::entries(&open(file), prefix.as_deref().unwrap_or("")).unwrap_or_else(|err| crashln!("{FAIL} {err}"));
+
+    for entry in &entries {
+        match entry.expires {
+            Some(at) => println!("{} {}", entry.key, format!("(expires in {}s)", at.saturating_sub(now()).div_ceil(1000)).bright_black()),
+            None => println!("{}", entry.key),
+        }
+    }
+}
+
+pub fn get(file: &str, key: &str) {
+    let entries = kv::entries(&open(file), key).unwrap_or_else(|err| crashln!("{FAIL} {err}"));
+
+    match entries.into_iter().find(|entry| entry.key == key) {
+        Some(entry) => println!("{}", serde_json::to_string_pretty(&entry.value).unwrap_or_default()),
+        None => crashln!("{FAIL} Key {} not found in {file}", key.bold()),
+    }
+}
+
+pub fn set(file: &str, key: String, value: &str, ttl: Option<u64>) {
+    let entry = Entry {
+        key,
+        value: parse_value(value),
+        expires: ttl.map(kv::expires_in),
+    };
+
+    match kv::insert(&open(file), &[entry]) {
+        Ok(_) => println!("{SUCCESS} Saved key in {file}"),
+        Err(err) => crashln!("{FAIL} {err}"),
+    }
+}
+
+pub fn del(file: &str, key: &str) {
+    match kv::remove(&open(file), key) {
+        Ok(true) => println!("{SUCCESS} Removed {} from {file}", key.bold()),
+        Ok(false) => crashln!("{FAIL} Key {} not found in {file}", key.bold()),
+        Err(err) => crashln!("{FAIL} {err}"),
+    }
+}
+
+/// Writes one json object per line, to stdout unless `output` is given.
+pub fn export(file: &str, output: Option<PathBuf>) {
+    let entries = kv::entries(&open(file), "").unwrap_or_else(|err| crashln!("{FAIL} {err}"));
+
+    let mut writer: Box<dyn Write> = match &output {
+        Some(path) => Box::new(fs::File::create(path).unwrap_or_else(|err| crashln!("{FAIL} Cannot create {}, {err}", path.display()))),
+        None => Box::new(io::stdout().lock()),
+    };
+
+    for entry in &entries {
+        let line = serde_json::to_string(entry).unwrap_or_default();
+        if let Err(err) = writeln!(writer, "{line}") {
+            crashln!("{FAIL} Cannot write export, {err}");
+        }
+    }
+
+    if let Some(path) = output {
+        println!("{SUCCESS} Exported {} key(s) to {}", entries.len(), path.display());
+    }
+}
+
+/// Reads json lines from `input`, or stdin, and writes them all in one transaction.
+pub fn import(file: &str, input: Option<PathBuf>) {
+    let reader: Box<dyn BufRead> = match &input {
+        Some(path) => Box::new(io::BufReader::new(fs::File::open(path).unwrap_or_else(|err| crashln!("{FAIL} Cannot open {}, {err}", path.display())))),
+        None => Box::new(io::stdin().lock()),
+    };
+
+    let mut entries = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.unwrap_or_else(|err| crashln!("{FAIL} Cannot read import, {err}"));
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match serde_json::from_str::<Entry>(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(err) => crashln!("{FAIL} Invalid entry on line {}, {err}", index + 1),
+        }
+    }
+
+    match kv::insert(&open(file), &entries) {
+        Ok(_) => println!("{SUCCESS} Imported {} key(s) into {file}", entries.len()),
+        Err(err) => crashln!("{FAIL} {err}"),
+    }
+}
@@ -0,0 +1,85 @@
+use clap::ValueEnum;
+use colored::Colorize;
+use macros_rs::fmt::crashln;
+use std::{fs, path::PathBuf};
+
+use crate::{
+    helpers::prelude::*,
+    structs::template::{ApiApp, ApiTests, ProjectConfig, SiteApp, SiteIndex, SiteTests, Template},
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Starter {
+    /// Json routes for an http api
+    Api,
+    /// Html pages served from static/
+    Site,
+}
+
+fn render(template: impl Template) -> String {
+    match template.render() {
+        Ok(contents) => format!("{contents}\n"),
+        Err(err) => crashln!("{FAIL} Failed to render project template, {err}"),
+    }
+}
+
+/// Writes a working project into `dir`, refusing to overwrite existing files.
+pub fn create(dir: PathBuf, starter: Starter, tests: bool) {
+    let name = match fs::canonicalize(&dir).ok().as_ref().unwrap_or(&dir).file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => "app".into(),
+    };
+
+    let workers = match tests {
+        true => vec!["app.rt", "tests.rt"],
+        false => vec!["app.rt"],
+    };
+
+    let mut files = vec![("config.toml", render(ProjectConfig { name: &name, workers }))];
+
+    match starter {
+        Starter::Api => files.push(("app.rt", render(ApiApp { name: &name }))),
+        Starter::Site => {
+            files.push(("app.rt", render(SiteApp)));
+            files.push(("static/index.html", render(SiteIndex { name: &name })));
+        }
+    }
+
+    if tests {
+        match starter {
+            Starter::Api => files.push(("tests.rt", render(ApiTests { name: &name }))),
+            Starter::Site => files.push(("tests.rt", render(SiteTests { name: &name }))),
+        }
+    }
+
+    let existing: Vec<String> = files.iter().map(|(path, _)| dir.join(path)).filter(|path| path.exists()).map(|path| path.display().to_string()).collect();
+
+    if !existing.is_empty() {
+        crashln!("{FAIL} Refusing to overwrite existing files: {}", existing.join(", "));
+    }
+
+    if let Err(err) = fs::create_dir_all(dir.join("static")) {
+        crashln!("{FAIL} Failed to create {}, {err}", dir.display())
+    }
+
+    for (path, contents) in files {
+        match fs::write(dir.join(path), contents) {
+            Ok(_) => println!("{DASH} created {}", dir.join(path).display()),
+            Err(err) => crashln!("{FAIL} Failed to write {}, {err}", dir.join(path).display()),
+        }
+    }
+
+    println!("{SUCCESS} project {} is ready", name.bold());
+
+    println!("\nget started with");
+
+    if dir != std::path::Path::new(".") {
+        println!("   cd {}", dir.display());
+    }
+
+    println!("   script");
+
+    if tests {
+        println!("   script test");
+    }
+}
@@ -0,0 +1,114 @@
+use colored::Colorize;
+use global_placeholders::global;
+use macros_rs::fmt::crashln;
+use rhai::{Dynamic, ParseErrorType, AST};
+use std::path::PathBuf;
+
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+use crate::{
+    helpers::prelude::*,
+    http::{self, Request},
+    routes::cache,
+    structs::config::Config,
+};
+
+const HELP: &str = "\
+:help     show this message
+:scope    list the variables in scope
+:quit     exit the repl (or ctrl-d)
+
+Input continues over several lines until it parses, an empty line runs it as is.";
+
+/// Whether parsing failed only because the input stopped early.
+fn is_incomplete(err: &ParseErrorType) -> bool { matches!(err, ParseErrorType::UnexpectedEOF | ParseErrorType::MissingToken(..)) }
+
+/// Evaluates lines with the same engine and modules as route handlers.
+pub fn repl(config: Config, url: String) {
+    let engine = http::engine(&config);
+    let mut scope = http::scope(&Request::synthetic("GET", &url, vec![], String::new()));
+    let mut functions = AST::empty();
+
+    let history = match cache::is_memory() {
+        true => None,
+        false => Some(PathBuf::from(global!("dirs.history"))),
+    };
+
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(err) => crashln!("{FAIL} Failed to start repl, {err}"),
+    };
+
+    if let Some(path) = &history {
+        let _ = editor.load_history(path);
+    }
+
+    println!("{} {} {DASH} type {} for help", "script".bold(), env!("CARGO_PKG_VERSION"), ":help".cyan());
+
+    let mut input = String::new();
+
+    loop {
+        let prompt = match input.is_empty() {
+            true => "> ",
+            false => ". ",
+        };
+
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) if !input.is_empty() => {
+                input.clear();
+                continue;
+            }
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+            Err(err) => crashln!("{FAIL} Failed to read input, {err}"),
+        };
+
+        if input.is_empty() {
+            match line.trim() {
+                "" => continue,
+                ":quit" | ":exit" => break,
+                ":help" => {
+                    println!("{HELP}");
+                    continue;
+                }
+                ":scope" => {
+                    scope.iter().for_each(|(name, _, value)| println!("{} = {value:?}", name.cyan()));
+                    continue;
+                }
+                _ => {}
+            }
+        }
+
+        let force = line.trim().is_empty();
+        input = format!("{input}{line}\n");
+
+        let ast = match engine.compile(&input) {
+            Ok(ast) => ast,
+            Err(err) if is_incomplete(&err.0) && !force => continue,
+            Err(err) => {
+                println!("{} {err}", "error".red().bold());
+                let _ = editor.add_history_entry(input.trim_end());
+                input.clear();
+                continue;
+            }
+        };
+
+        let _ = editor.add_history_entry(input.trim_end());
+        input.clear();
+
+        let program = functions.merge(&ast);
+        functions += ast.clone_functions_only();
+
+        match engine.eval_ast_with_scope::<Dynamic>(&mut scope, &program) {
+            Ok(value) if value.is_unit() => {}
+            Ok(value) => println!("{}", format!("{value:?}").white()),
+            Err(err) => println!("{} {err}", "error".red().bold()),
+        }
+    }
+
+    if let Some(path) = &history {
+        if let Err(err) = editor.save_history(path) {
+            log::warn!(err = err.to_string(), "Cannot save repl history");
+        }
+    }
+}
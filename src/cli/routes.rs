@@ -0,0 +1,158 @@
+use colored::Colorize;
+use macros_rs::fmt::crashln;
+use std::fs;
+
+use crate::{
+    helpers::prelude::*,
+    routes::{
+        cache,
+        lint::{self, Source},
+        parse,
+        r#match::{self, Reason},
+        RtKind,
+    },
+    structs::config::Config,
+};
+
+fn sources(config: &Config) -> Vec<Source> {
+    let mut sources = Vec::new();
+
+    for worker in &config.workers {
+        let contents = match fs::read_to_string(worker) {
+            Ok(contents) => contents,
+            Err(err) => crashln!("{FAIL} Failed to read {}, {err}", worker.display()),
+        };
+
+        match lint::parse_file(worker.to_owned(), &contents) {
+            Ok(mut parsed) => sources.append(&mut parsed),
+            Err(problem) => crashln!("{FAIL} Failed to parse {}:{}, {}", problem.file.display(), problem.line, problem.message),
+        }
+    }
+
+    sources
+}
+
+fn group(source: &Source) -> u8 {
+    match source.kind {
+        RtKind::Normal if r#match::is_wildcard(&source.route) => 0,
+        RtKind::Normal if lint::placeholders(&source.route.route).is_empty() => 1,
+        RtKind::Normal => 2,
+        RtKind::NotFound => 3,
+        RtKind::Wildcard => 4,
+    }
+}
+
+fn pattern(source: &Source) -> String {
+    match source.kind {
+        RtKind::NotFound => "404".into(),
+        RtKind::Wildcard => "*".into(),
+        RtKind::Normal if r#match::is_wildcard(&source.route) => format!("{}/**", source.route.route),
+        RtKind::Normal => source.route.route.to_string(),
+    }
+}
+
+fn params(source: &Source) -> String {
+    match source.route.args.as_ref().filter(|args| !args.is_empty()) {
+        Some(args) => args.iter().map(|arg| format!("{arg}: string")).collect::<Vec<_>>().join(", "),
+        None => "-".into(),
+    }
+}
+
+fn cfg(source: &Source) -> String {
+    match source.route.cfg.as_ref().filter(|cfg| !cfg.is_empty()) {
+        Some(cfg) => {
+            let mut entries: Vec<String> = cfg.iter().map(|(key, value)| format!("{key}={value}")).collect();
+            entries.sort();
+            entries.join(", ")
+        }
+        None => "-".into(),
+    }
+}
+
+pub fn table(config: Config) {
+    let mut sources = sources(&config);
+
+    sources.sort_by(|a, b| {
+        let key = |s: &Source| (group(s), if group(s) == 2 { r#match::precedence(&s.route.route) } else { Default::default() }, s.route.route.to_string());
+        key(a).cmp(&key(b))
+    });
+
+    let rows: Vec<[String; 6]> = sources
+        .iter()
+        .map(|source| ["ANY".into(), pattern(source), params(source), cfg(source), source.route.fn_name.to_string(), source.location()])
+        .collect();
+
+    let headers = ["METHOD", "PATTERN", "PARAMS", "CFG", "HANDLER", "SOURCE"];
+    let widths: Vec<usize> = (0..headers.len()).map(|i| rows.iter().map(|row| row[i].len()).chain([headers[i].len()]).max().unwrap_or(0)).collect();
+
+    let header = headers.iter().enumerate().map(|(i, cell)| format!("{cell:<0$}", widths[i])).collect::<Vec<_>>().join("  ");
+
+    println!("{}", header.trim_end().bold());
+
+    for row in rows {
+        let cells: Vec<String> = row.iter().enumerate().map(|(i, cell)| format!("{cell:<0$}", widths[i])).collect();
+        println!("{}  {}  {}  {}  {}  {}", cells[0].white(), cells[1].cyan(), cells[2], cells[3].yellow(), cells[4].bright_cyan().bold(), cells[5].trim_end().white());
+    }
+
+    let not_found = sources.iter().find(|s| s.kind == RtKind::NotFound);
+    let fallback = sources.iter().find(|s| s.kind == RtKind::Wildcard);
+
+    println!();
+    match (not_found, fallback) {
+        (Some(not_found), Some(fallback)) => {
+            println!("{STAR} unmatched urls are handled by the 404 block at {}", not_found.location());
+            println!("{STAR} {} the * block at {} is unreachable while a 404 block exists", "warning".yellow(), fallback.location());
+        }
+        (Some(not_found), None) => println!("{STAR} unmatched urls are handled by the 404 block at {}", not_found.location()),
+        (None, Some(fallback)) => println!("{STAR} unmatched urls are handled by the * block at {}", fallback.location()),
+        (None, None) => println!("{STAR} unmatched urls receive the built-in 404 page"),
+    }
+}
+
+#[tokio_wrap::sync]
+pub fn explain(config: Config, url: String) {
+    let sources = sources(&config);
+    let path = url.split(['?', '#']).next().unwrap_or_default().to_string();
+
+    cache::use_memory();
+
+    let contents = match get_workers(&config.workers).await {
+        Ok(content) => content,
+        Err(err) => crashln!("{FAIL} Failed to read contents, {err}"),
+    };
+
+    if let Err(err) = parse::try_parse(&contents).await {
+        crashln!("{FAIL} Failed to parse contents, {err}")
+    };
+
+    let resolved = match r#match::resolve(&path).await {
+        Some(resolved) => resolved,
+        None => {
+            println!("{STAR} {} matches no route and there is no 404 or * block", path.cyan());
+            println!("{STAR} the built-in 404 page is returned");
+            return;
+        }
+    };
+
+    let location = |route: &crate::routes::Route| match sources.iter().find(|s| s.route.cache == route.cache) {
+        Some(source) => source.location(),
+        None => "unknown".into(),
+    };
+
+    println!("{STAR} {} {DASH} {} ({})", path.cyan(), resolved.route.fn_name.bright_cyan().bold(), location(&resolved.route).white());
+    println!("   {} {}", "route:".bold(), resolved.route.route);
+    println!("   {} {}", "reason:".bold(), resolved.reason);
+
+    if resolved.reason == Reason::Wildcard {
+        println!("   {} '{}' is the first path segment of the url", "prefix:".bold(), r#match::parse_slash(&path));
+    }
+
+    let names = resolved.route.args.to_owned().unwrap_or_default();
+    for (name, value) in names.iter().zip(resolved.args.iter()) {
+        println!("   {} {name} = {value:?}", "param:".bold());
+    }
+
+    for route in resolved.shadowed {
+        println!("   {} {} also matches but has lower precedence ({})", "shadowed:".yellow().bold(), route.route, location(&route).white());
+    }
+}
@@ -0,0 +1,249 @@
+use colored::Colorize;
+use macros_rs::fmt::crashln;
+use rhai::{Dynamic, EvalAltResult, ImmutableString, Map, ParseError};
+use tokio::runtime::Handle;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    database::kv,
+    helpers::prelude::*,
+    http::{self, Failure, Request},
+    routes::{
+        cache,
+        parse::{self, Case},
+    },
+    structs::config::Config,
+};
+
+struct Outcome {
+    file: PathBuf,
+    case: Case,
+    time: Duration,
+    /// Line of the failing statement and the reason, `None` when the test passed.
+    failure: Option<(usize, String)>,
+}
+
+impl Outcome {
+    fn location(&self) -> String { format!("{}:{}", self.file.display(), self.case.line) }
+}
+
+fn assert(ok: bool, message: &str) -> Result<(), Box<EvalAltResult>> {
+    match ok {
+        true => Ok(()),
+        false => Err(message.into()),
+    }
+}
+
+/// Sends a request through the same routing and handler pipeline as the server.
+fn call(config: &Arc<Config>, handle: &Handle, method: &str, url: &str, options: Map) -> Map {
+    let headers = match options.get("headers").and_then(|headers| headers.read_lock::<Map>().map(|map| map.to_owned())) {
+        Some(headers) => headers.into_iter().map(|(name, value)| (name.to_lowercase(), value.to_string())).collect(),
+        None => vec![],
+    };
+
+    let body = options.get("body").map(|body| body.to_string()).unwrap_or_default();
+    let request = Request::synthetic(method, url, headers, body);
+
+    let (body, content_type, status) = match handle.block_on(http::invoke(Arc::clone(config), request)) {
+        Ok(response) => response,
+        Err(Failure::NotFound(_)) => http::not_found(url),
+        Err(Failure::Runtime(err)) => http::server_error(&err),
+    };
+
+    let mut res = Map::new();
+    res.insert("status".into(), (status.as_u16() as i64).into());
+    res.insert("content_type".into(), content_type.to_string().into());
+    res.insert("body".into(), body.into());
+    res
+}
+
+/// Accepts `call(method, url)` and `call(method, url, #{ headers, body })`.
+fn call_syntax(symbols: &[ImmutableString], look_ahead: &str, _: &mut Dynamic) -> Result<Option<ImmutableString>, ParseError> {
+    let next = match symbols.len() {
+        1 => "(",
+        3 => ",",
+        5 if look_ahead == "," => ",",
+        6 if symbols[5] == ")" => return Ok(None),
+        2 | 4 | 6 => "$expr$",
+        5 | 7 => ")",
+        _ => return Ok(None),
+    };
+
+    Ok(Some(next.into()))
+}
+
+fn run_case(config: Arc<Config>, handle: Handle, case: &Case) -> Option<(usize, String)> {
+    let mut engine = http::engine(&config);
+    let shared = Arc::clone(&config);
+
+    // `call` is a reserved function name in rhai, so it is registered as syntax
+    engine.register_custom_syntax_with_state_raw("call", call_syntax, false, move |context, inputs, _| {
+        let method = context.eval_expression_tree(&inputs[0])?.to_string();
+        let url = context.eval_expression_tree(&inputs[1])?.to_string();
+        let options = match inputs.get(2) {
+            Some(options) => context.eval_expression_tree(options)?.try_cast::<Map>().unwrap_or_default(),
+            None => Map::new(),
+        };
+
+        Ok(call(&shared, &handle, &method, &url, options).into())
+    });
+
+    engine
+        .register_fn("assert", |ok: bool| assert(ok, "assertion failed"))
+        .register_fn("assert", |ok: bool, message: &str| assert(ok, message))
+        .register_fn("assert_eq", |left: Dynamic, right: Dynamic| assert(format!("{left:?}") == format!("{right:?}"), &format!("expected {right:?}, found {left:?}")))
+        .register_fn("assert_ne", |left: Dynamic, right: Dynamic| assert(format!("{left:?}") != format!("{right:?}"), &format!("expected a value other than {right:?}")));
+
+    let ast = match engine.compile(&case.body) {
+        Ok(ast) => ast,
+        Err(err) => return Some((case.offset + err.1.line().unwrap_or(1), err.0.to_string())),
+    };
+
+    match engine.run_ast(&ast) {
+        Ok(_) => None,
+        Err(err) => {
+            let line = case.offset + err.position().line().unwrap_or(1);
+            let message = match *err {
+                EvalAltResult::ErrorRuntime(value, _) => value.to_string(),
+                err => err.to_string(),
+            };
+            Some((line, message))
+        }
+    }
+}
+
+fn escape(text: &str) -> String { text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;") }
+
+fn junit(outcomes: &[Outcome], path: &Path) {
+    let mut files: Vec<&PathBuf> = outcomes.iter().map(|outcome| &outcome.file).collect();
+    files.dedup();
+
+    let failures = outcomes.iter().filter(|outcome| outcome.failure.is_some()).count();
+    let total: f64 = outcomes.iter().map(|outcome| outcome.time.as_secs_f64()).sum();
+
+    let mut xml = format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites tests=\"{}\" failures=\"{failures}\" time=\"{total:.3}\">\n", outcomes.len());
+
+    for file in files {
+        let suite: Vec<&Outcome> = outcomes.iter().filter(|outcome| &outcome.file == file).collect();
+        let failed = suite.iter().filter(|outcome| outcome.failure.is_some()).count();
+        let time: f64 = suite.iter().map(|outcome| outcome.time.as_secs_f64()).sum();
+        let name = escape(&file.display().to_string());
+
+        xml += &format!("  <testsuite name=\"{name}\" tests=\"{}\" failures=\"{failed}\" time=\"{time:.3}\">\n", suite.len());
+
+        for outcome in suite {
+            xml += &format!(
+                "    <testcase name=\"{}\" classname=\"{name}\" file=\"{name}\" line=\"{}\" time=\"{:.3}\"",
+                escape(&outcome.case.name),
+                outcome.case.line,
+                outcome.time.as_secs_f64()
+            );
+
+            match &outcome.failure {
+                Some((line, message)) => xml += &format!(">\n      <failure message=\"{}\">{name}:{line}: {}</failure>\n    </testcase>\n", escape(message), escape(message)),
+                None => xml += " />\n",
+            }
+        }
+
+        xml += "  </testsuite>\n";
+    }
+
+    xml += "</testsuites>\n";
+
+    if let Err(err) = fs::write(path, xml) {
+        crashln!("{FAIL} Failed to write {}, {err}", path.display())
+    }
+}
+
+/// Runs every `test` block in the workers, each with an empty kv sandbox.
+#[tokio_wrap::sync]
+pub fn test(config: Config, filter: Option<String>, report: Option<PathBuf>) {
+    cache::use_memory();
+
+    let contents = match get_workers(&config.workers).await {
+        Ok(content) => content,
+        Err(err) => crashln!("{FAIL} Failed to read contents, {err}"),
+    };
+
+    if let Err(err) = parse::try_parse(&contents).await {
+        crashln!("{FAIL} Failed to parse contents, {err}")
+    };
+
+    let config = Arc::new(config);
+    let sandbox = std::env::temp_dir().join(format!("script-test-{}", process::id()));
+    let mut outcomes: Vec<Outcome> = Vec::new();
+
+    for file in &config.workers {
+        let input = match fs::read_to_string(file) {
+            Ok(input) => input,
+            Err(err) => crashln!("{FAIL} Failed to read {}, {err}", file.display()),
+        };
+
+        let cases = match parse::cases(&input) {
+            Ok(cases) => cases,
+            Err(err) => crashln!("{FAIL} Failed to parse {}, {err}", file.display()),
+        };
+
+        for case in cases {
+            if filter.as_ref().is_some_and(|filter| !case.name.contains(filter.as_str())) {
+                continue;
+            }
+
+            let dir = sandbox.join(outcomes.len().to_string());
+            kv::sandbox(Some(dir.to_owned()));
+
+            let (config, handle, started) = (Arc::clone(&config), Handle::current(), Instant::now());
+            let (case, failure) = match tokio::task::spawn_blocking(move || {
+                let failure = run_case(config, handle, &case);
+                (case, failure)
+            })
+            .await
+            {
+                Ok(result) => result,
+                Err(err) => crashln!("{FAIL} Test runner panicked, {err}"),
+            };
+
+            let _ = fs::remove_dir_all(&dir);
+
+            let outcome = Outcome {
+                case,
+                failure,
+                file: file.to_owned(),
+                time: started.elapsed(),
+            };
+
+            match &outcome.failure {
+                None => println!("{} {} ({})", "PASS".green().bold(), outcome.case.name, outcome.location().white()),
+                Some((line, message)) => {
+                    println!("{} {} ({})", "FAIL".red().bold(), outcome.case.name, outcome.location().white());
+                    println!("     {}:{line}: {message}", outcome.file.display());
+                }
+            }
+
+            outcomes.push(outcome);
+        }
+    }
+
+    kv::sandbox(None);
+    let _ = fs::remove_dir_all(&sandbox);
+
+    if let Some(path) = report {
+        junit(&outcomes, &path);
+    }
+
+    let failed = outcomes.iter().filter(|outcome| outcome.failure.is_some()).count();
+    let summary = format!("{} passed, {failed} failed", outcomes.len() - failed);
+
+    match failed {
+        0 if outcomes.is_empty() => println!("\n{STAR} no tests found"),
+        0 => println!("\n{SUCCESS} {summary}"),
+        _ => crashln!("\n{FAIL} {summary}"),
+    }
+}
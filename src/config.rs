@@ -1,14 +1,119 @@
 use crate::{helpers::prelude::*, structs::config::*};
 
 use colored::Colorize;
+use mongodb::options::{ClientOptions, Credential, ServerAddress, Tls, TlsOptions};
 use macros_rs::fmt::{crashln, string};
 use pickledb::SerializationMethod;
-use std::{fs, path::PathBuf};
+use redis::IntoConnectionInfo;
+use std::{collections::BTreeSet, env, path::PathBuf, time::Duration};
+use toml::Value;
+
+/// Replaces `${VAR}` with the environment variable, or `default` in `${VAR:-default}` when unset or empty.
+fn interpolate(input: &str) -> Result<String, String> {
+    let mut output = String::new();
+    let mut rest = input;
+
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => return Err(format!("unclosed '${{' in \"{input}\"")),
+        };
+
+        let expr = &rest[start + 2..end];
+        let (name, default) = match expr.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (expr, None),
+        };
+
+        let value = env::var(name).ok().filter(|value| !value.is_empty() || default.is_none());
+
+        match value.or(default.map(str::to_string)) {
+            Some(value) => output += &format!("{}{value}", &rest[..start]),
+            None => return Err(format!("environment variable {name} is not set")),
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    Ok(output + rest)
+}
+
+/// Overlays `other` onto `base`, tables are merged key by key and anything else is replaced.
+fn merge(base: &mut Value, other: Value) {
+    match (base, other) {
+        (Value::Table(base), Value::Table(other)) => other.into_iter().for_each(|(key, value)| match base.get_mut(&key) {
+            Some(existing) => merge(existing, value),
+            None => {
+                base.insert(key, value);
+            }
+        }),
+        (base, other) => *base = other,
+    }
+}
+
+/// Matches each segment to an existing key ignoring case, so `ENV__API_URL` overrides `[env] API_URL`.
+/// Segments without a matching key are lowercased.
+fn resolve(value: &Value, segments: &[&str]) -> Vec<String> {
+    let mut current = Some(value);
+
+    segments
+        .iter()
+        .map(|segment| {
+            let existing = current.and_then(Value::as_table).and_then(|table| table.keys().find(|key| key.eq_ignore_ascii_case(segment)).cloned());
+            let key = existing.unwrap_or_else(|| segment.to_lowercase());
+            current = current.and_then(|value| value.get(&key));
+            key
+        })
+        .collect()
+}
+
+/// Applies `SCRIPT_SETTINGS__PORT=3000` style variables, `__` separates nested keys.
+fn overrides(value: &mut Value) {
+    for (name, raw) in env::vars() {
+        let Some(path) = name.strip_prefix("SCRIPT_") else { continue };
+        let keys = resolve(value, &path.split("__").collect::<Vec<_>>());
+
+        let parsed = match format!("value = {raw}").parse::<toml::Table>() {
+            Ok(mut table) => table.remove("value").unwrap_or(Value::String(raw)),
+            Err(_) => Value::String(raw),
+        };
+
+        let mut overlay = parsed;
+        for key in keys.iter().rev() {
+            overlay = Value::Table(toml::Table::from_iter([(key.to_owned(), overlay)]));
+        }
+
+        merge(value, overlay);
+    }
+}
+
+/// Interpolates every string, recording the paths that referenced a variable in `interpolated`.
+fn expand(value: &mut Value, path: &str, errors: &mut Vec<String>, interpolated: &mut BTreeSet<String>) {
+    match value {
+        Value::String(string) => match interpolate(string) {
+            Ok(expanded) => {
+                if string.contains("${") {
+                    interpolated.insert(path.to_string());
+                }
+                *string = expanded
+            }
+            Err(err) => errors.push(format!("{path}: {err}")),
+        },
+        Value::Array(items) => items.iter_mut().enumerate().for_each(|(index, item)| expand(item, &format!("{path}[{index}]"), errors, interpolated)),
+        Value::Table(table) => table.iter_mut().for_each(|(key, item)| match path {
+            "" => expand(item, key, errors, interpolated),
+            _ => expand(item, &format!("{path}.{key}"), errors, interpolated),
+        }),
+        _ => {}
+    }
+}
 
 impl Config {
     pub fn new() -> Self {
         Self {
             config_path: "config.toml".into(),
+            profile: None,
+            interpolated: BTreeSet::new(),
             env: None,
             database: None,
             workers: vec!["app.rt".into()],
@@ -16,26 +121,49 @@ impl Config {
                 cache: string!(".script"),
                 address: string!("127.0.0.1"),
                 port: 3500,
+                cache_format: CacheFormat::Ron,
             },
         }
     }
 
-    pub fn read(&self) -> Self { read_toml(self.config_path.to_owned()) }
+    pub fn from_cli(cli: &crate::Cli) -> Self { Config::new().set_path(&cli.config).set_profile(cli.profile.to_owned()).read() }
 
-    pub fn write_example(&self) {
-        let config_path = self.config_path.to_str().unwrap_or("");
+    /// Reads the config merged with its profile and `SCRIPT_*` overrides, expanding `${VAR}` and `${VAR:-default}` in every string.
+    pub fn read(&self) -> Self {
+        let path = self.config_path.to_str().unwrap_or("");
+        let mut value: Value = read_toml(self.config_path.to_owned());
+        let mut errors = Vec::new();
+        let mut interpolated = BTreeSet::new();
 
-        let contents = match toml::to_string(self) {
-            Ok(contents) => contents,
-            Err(err) => crashln!("Cannot parse config.\n{}", string!(err).white()),
-        };
+        if let Some(profile) = self.profile_path() {
+            merge(&mut value, read_toml(profile));
+        }
+
+        overrides(&mut value);
+
+        expand(&mut value, "", &mut errors, &mut interpolated);
 
-        if let Err(err) = fs::write(&self.config_path, contents) {
-            crashln!("Error writing config to {config_path}.\n{}", string!(err).white())
+        if !errors.is_empty() {
+            crashln!("Cannot interpolate {path}.\n{}", errors.join("\n").white());
         }
 
-        log::info!(path = config_path, created = true, "config");
-        crashln!("Failed to find config file.\n\nAn default config has been written to {config_path}\nPlease use this to setup your app config correctly.");
+        match value.try_into::<Config>() {
+            Ok(mut config) => {
+                // every mongo field is optional, so a section with only named connections still yields a default
+                if let Some(mongo) = config.database.as_mut().and_then(|database| database.mongo.as_mut()) {
+                    let named = !mongo.named.is_empty();
+                    mongo.default = mongo.default.take().filter(|default| !named || default.server.is_some() || default.advanced.is_some());
+                }
+
+                Config {
+                    config_path: self.config_path.to_owned(),
+                    profile: self.profile.to_owned(),
+                    interpolated,
+                    ..config
+                }
+            }
+            Err(err) => crashln!("Cannot parse {path}.\n{}", string!(err).white()),
+        }
     }
 
     pub fn set_path(&mut self, config_path: &String) -> &mut Self {
@@ -43,6 +171,22 @@ impl Config {
         return self;
     }
 
+    pub fn set_profile(&mut self, profile: Option<String>) -> &mut Self {
+        self.profile = profile;
+        self
+    }
+
+    /// `config.prod.toml` next to `config.toml` for the `prod` profile.
+    pub fn profile_path(&self) -> Option<PathBuf> {
+        let profile = self.profile.as_ref()?;
+        let stem = self.config_path.file_stem()?.to_string_lossy();
+
+        match self.config_path.extension() {
+            Some(ext) => Some(self.config_path.with_file_name(format!("{stem}.{profile}.{}", ext.to_string_lossy()))),
+            None => Some(self.config_path.with_file_name(format!("{stem}.{profile}"))),
+        }
+    }
+
     pub fn kv_serialization_method(&self) -> Option<SerializationMethod> {
         let database = self.database.to_owned()?.kv?;
 
@@ -54,8 +198,182 @@ impl Config {
         }
     }
 
+    /// Every problem in the config at once, so they can be fixed in one go.
+    pub fn validate(&self) -> Vec<Problem> { self.problems(true) }
+
+    /// The `[database]` sections are only checked when `databases` is set.
+    fn problems(&self, databases: bool) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        let mut problem = |key: &str, message: String| problems.push(Problem { key: key.into(), message });
+
+        if self.workers.is_empty() {
+            problem("workers", "at least one worker file is required".into());
+        }
+
+        for (index, worker) in self.workers.iter().enumerate() {
+            if !worker.is_file() {
+                problem(&format!("workers[{index}]"), format!("worker file {} does not exist", worker.display()));
+            }
+        }
+
+        if self.settings.cache.trim().is_empty() {
+            problem("settings.cache", "expected a directory or \"memory\"".into());
+        }
+
+        if self.settings.address.trim().is_empty() {
+            problem("settings.address", "expected an address such as 127.0.0.1".into());
+        }
+
+        let Some(database) = self.database.as_ref().filter(|_| databases) else { return problems };
+
+        if let Some(kv) = &database.kv {
+            if !matches!(&*kv.method, "json" | "default" | "yaml" | "yml" | "binary" | "bin") {
+                problem("database.kv.method", format!("unknown method '{}', expected json, yaml or binary", kv.method));
+            }
+        }
+
+        for (key, redis) in database.redis.iter().flat_map(|redis| redis.keyed("database.redis")) {
+            if let Err(err) = redis.server.as_str().into_connection_info() {
+                problem(&format!("{key}.server"), format!("{err}, expected redis://host:port"));
+            }
+        }
+
+        for (key, mongo) in database.mongo.iter().flat_map(|mongo| mongo.keyed("database.mongo")) {
+            match (&mongo.server, &mongo.advanced) {
+                (Some(_), Some(_)) => problem(&key, "set either server or advanced, not both".into()),
+                (None, None) => problem(&key, "expected server or advanced".into()),
+                (Some(server), None) if !server.starts_with("mongodb://") && !server.starts_with("mongodb+srv://") => {
+                    problem(&format!("{key}.server"), format!("'{server}' is not a mongodb:// or mongodb+srv:// url"))
+                }
+                (None, Some(advanced)) => {
+                    if advanced.address.trim().is_empty() {
+                        problem(&format!("{key}.advanced.address"), "expected a hostname".into());
+                    }
+                    if advanced.port == 0 || advanced.port > u16::MAX as u64 {
+                        problem(&format!("{key}.advanced.port"), format!("{} is not a valid port", advanced.port));
+                    }
+                    if let Some(auth) = &advanced.auth {
+                        if auth.username.is_empty() {
+                            problem(&format!("{key}.advanced.auth.username"), "expected a username".into());
+                        }
+                    }
+                    if let Some(tls) = &advanced.tls {
+                        for (field, file) in [("ca_file", &tls.ca_file), ("cert_key_file", &tls.cert_key_file)] {
+                            if let Some(file) = file.as_ref().filter(|file| !file.is_file()) {
+                                problem(&format!("{key}.advanced.tls.{field}"), format!("{} does not exist", file.display()));
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for (key, sqlite) in database.sqlite.iter().flat_map(|sqlite| sqlite.keyed("database.sqlite")) {
+            if sqlite.path.as_os_str().is_empty() || sqlite.path.as_os_str() == ":memory:" {
+                problem(&format!("{key}.path"), "expected a database file, in-memory databases are not shared between pooled connections".into());
+            } else if let Some(dir) = sqlite.path.parent().filter(|dir| !dir.as_os_str().is_empty() && !dir.is_dir()) {
+                problem(&format!("{key}.path"), format!("directory {} does not exist", dir.display()));
+            }
+        }
+
+        if let Some(migrations) = &database.migrations {
+            let connection = migrations.connection.as_deref();
+
+            if database.sqlite.as_ref().and_then(|sqlite| sqlite.get(connection)).is_none() {
+                match connection {
+                    Some(name) => problem("database.migrations.connection", format!("no sqlite connection named '{name}', add a [database.sqlite.{name}] section")),
+                    None => problem("database.migrations", "migrations need a [database.sqlite] connection".into()),
+                }
+            }
+
+            let dir = migrations.dir();
+            if migrations.on_start && !dir.is_dir() {
+                problem("database.migrations.dir", format!("directory {} does not exist", dir.display()));
+            }
+        }
+
+        problems
+    }
+
+    /// Crashes listing every problem found by [`Config::validate`], skipping
+    /// the `[database]` sections for commands that never run a script.
+    pub fn ensure_valid(&self, databases: bool) {
+        let problems = self.problems(databases);
+
+        if !problems.is_empty() {
+            let list: Vec<String> = problems.iter().map(|problem| format!("{} {}", problem.key.white(), problem.message)).collect();
+            crashln!("{FAIL} Invalid config {}\n\n{}\n\nRun {} for details.", self.config_path.display(), list.join("\n"), "script config check".cyan());
+        }
+    }
+
+    pub fn memory_cache(&self) -> bool { self.settings.cache == "memory" }
+
     pub fn override_port(&mut self, port: u16) { self.settings.port = port; }
     pub fn override_cache(&mut self, cache: String) { self.settings.cache = cache; }
     pub fn override_address(&mut self, address: String) { self.settings.address = address; }
     pub fn get_address(&self) -> (String, u16) { (self.settings.address.to_owned(), self.settings.port.to_owned()) }
 }
+
+impl<T> Connections<T> {
+    /// The unnamed connection for `None`, otherwise the named one.
+    pub fn get(&self, name: Option<&str>) -> Option<&T> {
+        match name {
+            Some(name) => self.named.get(name),
+            None => self.default.as_ref(),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Option<&str>, &T)> { self.default.iter().map(|config| (None, config)).chain(self.named.iter().map(|(name, config)| (Some(name.as_str()), config))) }
+
+    /// Every connection with its TOML path under `section`.
+    pub fn keyed(&self, section: &str) -> Vec<(String, &T)> {
+        self.iter()
+            .map(|(name, config)| match name {
+                Some(name) => (format!("{section}.{name}"), config),
+                None => (section.to_string(), config),
+            })
+            .collect()
+    }
+}
+
+impl MigrationsConfig {
+    pub fn dir(&self) -> PathBuf { self.dir.to_owned().unwrap_or_else(|| PathBuf::from("migrations")) }
+}
+
+impl MongoAdvanced {
+    /// Driver options for connecting without a `server` url.
+    pub fn client_options(&self) -> ClientOptions {
+        let host = ServerAddress::Tcp {
+            host: self.address.to_owned(),
+            port: u16::try_from(self.port).ok(),
+        };
+
+        let credential = self.auth.as_ref().map(|auth| {
+            Credential::builder()
+                .username(auth.username.to_owned())
+                .password(auth.password.to_owned())
+                .source(auth.source.to_owned())
+                .build()
+        });
+
+        let tls = self.tls.as_ref().map(|tls| {
+            Tls::Enabled(
+                TlsOptions::builder()
+                    .ca_file_path(tls.ca_file.to_owned())
+                    .cert_key_file_path(tls.cert_key_file.to_owned())
+                    .allow_invalid_certificates(tls.allow_invalid_certificates)
+                    .build(),
+            )
+        });
+
+        ClientOptions::builder()
+            .hosts(vec![host])
+            .credential(credential)
+            .tls(tls)
+            .app_name(self.app_name.to_owned())
+            .connect_timeout(self.connect_timeout_ms.map(Duration::from_millis))
+            .server_selection_timeout(self.server_selection_timeout_ms.map(Duration::from_millis))
+            .build()
+    }
+}
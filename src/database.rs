@@ -6,3 +6,10 @@ pub use mongo::*;
 
 pub mod redis;
 pub use redis::*;
+
+pub mod sqlite;
+pub use sqlite::*;
+
+pub mod migrate;
+pub mod pool;
+pub mod pubsub;
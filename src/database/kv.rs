@@ -1,72 +1,410 @@
-use crate::{prelude::*, structs::config::Config};
-use pickledb::{PickleDb, PickleDbDumpPolicy};
+use crate::globals;
+use pickledb::{PickleDb, SerializationMethod};
+use redb::{Database, ReadOnlyTable, ReadableDatabase, ReadableTable, Table, TableDefinition};
 use rhai::{plugin::*, FnNamespace};
-use std::cell::RefCell;
+use serde::{Deserialize, Serialize};
 
-fn load(path: String) -> Option<PickleDb> {
-    let config = Config::new().set_path(&crate::Cli::parse().config).read();
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-    if !file_exists!(&path) {
-        PickleDb::new(&path, PickleDbDumpPolicy::AutoDump, config.kv_serialization_method()?);
+const VALUES: TableDefinition<&str, &[u8]> = TableDefinition::new("kv");
+const EXPIRY: TableDefinition<&str, u64> = TableDefinition::new("kv_expiry");
+const MAGIC: [u8; 9] = [b'r', b'e', b'd', b'b', 0x1A, 0x0A, 0xA9, 0x0D, 0x0A];
+
+static SANDBOX: RwLock<Option<PathBuf>> = RwLock::new(None);
+static STORES: Mutex<BTreeMap<PathBuf, Arc<Database>>> = Mutex::new(BTreeMap::new());
+
+/// Redirects every `kv::load` path into `dir`, used to isolate test runs.
+/// Stores opened inside the previous sandbox are closed.
+pub fn sandbox(dir: Option<PathBuf>) {
+    let mut sandbox = SANDBOX.write().unwrap();
+
+    if let Some(previous) = sandbox.as_ref() {
+        STORES.lock().unwrap().retain(|path, _| !path.starts_with(previous));
     }
 
-    match PickleDb::load(path, PickleDbDumpPolicy::AutoDump, config.kv_serialization_method()?) {
-        Ok(db) => Some(db),
-        Err(_) => None,
+    *sandbox = dir;
+}
+
+fn resolve(path: String) -> PathBuf {
+    match SANDBOX.read().unwrap().as_ref() {
+        Some(dir) => dir.join(path.trim_start_matches('/')),
+        None => PathBuf::from(path),
     }
 }
 
-// add .iter() method
+fn is_redb(path: &Path) -> bool {
+    let mut header = [0; MAGIC.len()];
+    match fs::File::open(path) {
+        Ok(mut file) => std::io::Read::read_exact(&mut file, &mut header).is_ok() && header == MAGIC,
+        Err(_) => false,
+    }
+}
+
+/// Reads a pickledb file, trying the configured method before the others.
+fn read_pickledb(path: &Path, method: SerializationMethod) -> Option<PickleDb> {
+    let methods = [method, SerializationMethod::Json, SerializationMethod::Yaml, SerializationMethod::Bin, SerializationMethod::Cbor];
+    methods.into_iter().find_map(|method| PickleDb::load_read_only(path, method).ok())
+}
+
+/// Moves a pickledb file aside to `<path>.pickledb` and copies its keys into a new store.
+fn migrate(path: &Path, method: SerializationMethod) -> Result<Database, String> {
+    let Some(old) = read_pickledb(path, method) else {
+        return Err(format!("Cannot open kv store {}, it is neither a redb nor a pickledb file", path.display()));
+    };
+
+    let backup = PathBuf::from(format!("{}.pickledb", path.display()));
+    fs::rename(path, &backup).map_err(|err| format!("Cannot move {} aside, {err}", path.display()))?;
+
+    let keys = old.get_all();
+    let db = Database::create(path).map_err(|err| format!("Cannot create kv store {}, {err}", path.display()))?;
+    let copied = write(&db, |store| {
+        let mut copied = 0;
+        for key in &keys {
+            // bincode cannot decode into a json value, but the old kv module only ever wrote strings
+            let value = old.get::<serde_json::Value>(key).or_else(|| old.get::<String>(key).map(serde_json::Value::String));
+            if let Some(value) = value {
+                store.values.insert(&**key, &*serde_json::to_vec(&value).unwrap_or_default())?;
+                copied += 1;
+            }
+        }
+        Ok(copied)
+    });
+
+    let failed = match copied {
+        Ok(copied) if copied == keys.len() => {
+            log::info!("migrated pickledb store {} ({copied} keys), the original is kept at {}", path.display(), backup.display());
+            return Ok(db);
+        }
+        Ok(copied) => format!("only {copied} of {} keys could be read", keys.len()),
+        Err(err) => err.to_string(),
+    };
+
+    drop(db);
+    let _ = fs::rename(&backup, path);
+    Err(format!("Cannot migrate kv store {}, {failed}", path.display()))
+}
+
+/// Opens the store at `path` once and shares the handle with every later caller.
+pub fn open(path: String) -> Result<Arc<Database>, String> {
+    let Some(method) = globals::config().and_then(|config| config.kv_serialization_method()) else {
+        return Err("kv is not configured, add a [database.kv] section".into());
+    };
+
+    let path = resolve(path);
+    let mut stores = STORES.lock().unwrap();
+
+    if let Some(db) = stores.get(&path) {
+        return Ok(Arc::clone(db));
+    }
+
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        let _ = fs::create_dir_all(parent);
+    }
+
+    let db = match path.exists() && !is_redb(&path) {
+        true => migrate(&path, method)?,
+        false => Database::create(&path).map_err(|err| format!("Cannot open kv store {}, {err}", path.display()))?,
+    };
+
+    write(&db, |store| store.purge()).map_err(|err| format!("Cannot open kv store {}, {err}", path.display()))?;
+
+    let db = Arc::new(db);
+    stores.insert(path, Arc::clone(&db));
+
+    Ok(db)
+}
+
+fn now() -> u64 { SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis() as u64).unwrap_or_default() }
+
+/// The expiry timestamp `ttl` seconds from now, saturating instead of wrapping into the past.
+pub fn expires_in(ttl: u64) -> u64 { now().saturating_add(ttl.saturating_mul(1000)) }
+
+/// Values are kept as json so maps, arrays and numbers round-trip.
+fn encode(value: &Dynamic) -> Result<Vec<u8>, Box<EvalAltResult>> { serde_json::to_vec(value).map_err(|err| format!("Cannot store value, {err}").into()) }
+
+fn decode(bytes: &[u8]) -> Dynamic { serde_json::from_slice(bytes).unwrap_or(Dynamic::UNIT) }
+
+/// Both tables of a store inside one write transaction.
+struct Store<'t> {
+    values: Table<'t, &'static str, &'static [u8]>,
+    expiry: Table<'t, &'static str, u64>,
+}
+
+impl Store<'_> {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, redb::Error> {
+        if self.expiry.get(key)?.is_some_and(|at| at.value() <= now()) {
+            return Ok(None);
+        }
+        Ok(self.values.get(key)?.map(|value| value.value().to_vec()))
+    }
+
+    /// Stores `value` under `key`, replacing any previous expiry.
+    fn put(&mut self, key: &str, value: &[u8], expires: Option<u64>) -> Result<(), redb::Error> {
+        self.values.insert(key, value)?;
+        match expires {
+            Some(at) => self.expiry.insert(key, at)?,
+            None => self.expiry.remove(key)?,
+        };
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> Result<bool, redb::Error> {
+        let live = self.get(key)?.is_some();
+        self.values.remove(key)?;
+        self.expiry.remove(key)?;
+        Ok(live)
+    }
+
+    /// Drops every expired key, run whenever a store is opened.
+    fn purge(&mut self) -> Result<(), redb::Error> {
+        let now = now();
+        let expired = self.expiry.extract_if(|_, at| at <= now)?.map(|entry| Ok(entry?.0.value().to_string())).collect::<Result<Vec<String>, redb::Error>>()?;
+        for key in expired {
+            self.values.remove(&*key)?;
+        }
+        Ok(())
+    }
+}
+
+/// Both tables of a store inside one read transaction, hiding expired keys.
+struct Snapshot {
+    values: ReadOnlyTable<&'static str, &'static [u8]>,
+    expiry: ReadOnlyTable<&'static str, u64>,
+}
+
+impl Snapshot {
+    fn live(&self, key: &str, now: u64) -> Result<bool, redb::Error> { Ok(self.expiry.get(key)?.is_none_or(|at| at.value() > now)) }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, redb::Error> {
+        match self.live(key, now())? {
+            true => Ok(self.values.get(key)?.map(|value| value.value().to_vec())),
+            false => Ok(None),
+        }
+    }
+
+    /// Live entries in key order, starting at `prefix` and stopping once keys no longer match it.
+    fn entries(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>, redb::Error> {
+        let now = now();
+        let mut entries = Vec::new();
+
+        for entry in self.values.range(prefix..)? {
+            let (key, value) = entry?;
+            let key = key.value();
+
+            if !key.starts_with(prefix) {
+                break;
+            }
+            if self.live(key, now)? {
+                entries.push((key.to_string(), value.value().to_vec()));
+            }
+        }
+
+        Ok(entries)
+    }
+}
+
+/// Runs `f` in a single write transaction, committed only when it succeeds.
+fn write<T>(db: &Database, f: impl FnOnce(&mut Store) -> Result<T, redb::Error>) -> Result<T, redb::Error> {
+    let txn = db.begin_write()?;
+    let value = {
+        let mut store = Store { values: txn.open_table(VALUES)?, expiry: txn.open_table(EXPIRY)? };
+        f(&mut store)?
+    };
+    txn.commit()?;
+    Ok(value)
+}
+
+fn read<T>(db: &Database, f: impl FnOnce(&Snapshot) -> Result<T, redb::Error>) -> Result<T, redb::Error> {
+    let txn = db.begin_read()?;
+    f(&Snapshot { values: txn.open_table(VALUES)?, expiry: txn.open_table(EXPIRY)? })
+}
+
+fn script_err(err: redb::Error) -> Box<EvalAltResult> { format!("kv error, {err}").into() }
+
+/// One key as written by `script kv export`, `expires` is a unix timestamp in milliseconds.
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    pub key: String,
+    pub value: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+}
+
+/// Live entries whose key starts with `prefix`, in key order.
+pub fn entries(db: &Database, prefix: &str) -> Result<Vec<Entry>, String> {
+    let entries = read(db, |snapshot| {
+        let mut entries = Vec::new();
+        for (key, value) in snapshot.entries(prefix)? {
+            let expires = snapshot.expiry.get(&*key)?.map(|at| at.value());
+            let value = serde_json::from_slice(&value).unwrap_or_default();
+            entries.push(Entry { key, value, expires });
+        }
+        Ok(entries)
+    });
+
+    entries.map_err(|err| format!("Cannot read kv store, {err}"))
+}
+
+/// Writes every entry in a single transaction, so an import either fully applies or not at all.
+pub fn insert(db: &Database, entries: &[Entry]) -> Result<(), String> {
+    let written = write(db, |store| {
+        for entry in entries {
+            store.put(&entry.key, &serde_json::to_vec(&entry.value).unwrap_or_default(), entry.expires)?;
+        }
+        Ok(())
+    });
+
+    written.map_err(|err| format!("Cannot write kv store, {err}"))
+}
+
+pub fn remove(db: &Database, key: &str) -> Result<bool, String> { write(db, |store| store.remove(key)).map_err(|err| format!("Cannot write kv store, {err}")) }
 
 #[export_module]
 pub mod kv_db {
+    use super::{decode, encode, expires_in, now, read, script_err, write};
+    use rhai::{Array, Map, INT};
+
     #[derive(Clone)]
-    pub struct KV<'s> {
-        pub db: &'s RefCell<PickleDb>,
+    pub struct KV {
+        pub db: Arc<Database>,
+    }
+
+    #[rhai_fn(return_raw)]
+    pub fn load(path: String) -> Result<KV, Box<EvalAltResult>> { Ok(KV { db: super::open(path)? }) }
+
+    #[rhai_fn(global, pure, return_raw)]
+    pub fn set(conn: &mut KV, key: String, value: Dynamic) -> Result<(), Box<EvalAltResult>> {
+        let value = encode(&value)?;
+        write(&conn.db, |store| store.put(&key, &value, None)).map_err(script_err)
     }
 
-    pub fn load<'s>(path: String) -> KV<'s> {
-        // add error handling with error messages
-        let db = RefCell::new(super::load(path).unwrap());
-        KV { db: Box::leak(Box::new(db)) }
+    /// Like `set`, but the key disappears after `ttl` seconds.
+    #[rhai_fn(global, pure, return_raw, name = "set")]
+    pub fn set_ttl(conn: &mut KV, key: String, value: Dynamic, ttl: INT) -> Result<(), Box<EvalAltResult>> {
+        let value = encode(&value)?;
+        let expires = expires_in(ttl.max(0) as u64);
+        write(&conn.db, |store| store.put(&key, &value, Some(expires))).map_err(script_err)
+    }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn get(conn: KV, key: String) -> Result<Dynamic, Box<EvalAltResult>> {
+        let value = read(&conn.db, |snapshot| snapshot.get(&key)).map_err(script_err)?;
+        Ok(value.map(|value| decode(&value)).unwrap_or(Dynamic::UNIT))
     }
 
     #[rhai_fn(global, pure, return_raw)]
-    pub fn set(conn: &mut KV, key: String, value: String) -> Result<(), Box<EvalAltResult>> {
-        let mut db = conn.db.borrow_mut();
-        match db.set(&key, &value) {
-            Ok(_) => Ok(()),
-            Err(err) => Err(err.to_string().into()),
-        }
+    pub fn del(conn: &mut KV, key: String) -> Result<bool, Box<EvalAltResult>> { write(&conn.db, |store| store.remove(&key)).map_err(script_err) }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn exists(conn: KV, key: String) -> Result<bool, Box<EvalAltResult>> { read(&conn.db, |snapshot| Ok(snapshot.get(&key)?.is_some())).map_err(script_err) }
+
+    /// Sets `key` to expire in `ttl` seconds, returns whether it exists.
+    #[rhai_fn(global, pure, return_raw)]
+    pub fn expire(conn: &mut KV, key: String, ttl: INT) -> Result<bool, Box<EvalAltResult>> {
+        let expires = expires_in(ttl.max(0) as u64);
+        write(&conn.db, |store| match store.get(&key)? {
+            Some(value) => store.put(&key, &value, Some(expires)).map(|_| true),
+            None => Ok(false),
+        })
+        .map_err(script_err)
     }
 
-    #[rhai_fn(global)]
-    pub fn get(conn: KV, key: String) -> String {
-        let db = conn.db.borrow();
-        match db.get::<String>(&key) {
-            Some(data) => data,
-            None => string!(""),
-        }
+    /// Seconds until `key` expires, -1 when it never does and -2 when it is missing.
+    #[rhai_fn(global, return_raw)]
+    pub fn ttl(conn: KV, key: String) -> Result<INT, Box<EvalAltResult>> {
+        read(&conn.db, |snapshot| {
+            if snapshot.get(&key)?.is_none() {
+                return Ok(-2);
+            }
+            match snapshot.expiry.get(&*key)? {
+                Some(at) => Ok((at.value().saturating_sub(now())).div_ceil(1000) as INT),
+                None => Ok(-1),
+            }
+        })
+        .map_err(script_err)
     }
 
-    #[rhai_fn(global, pure)]
-    pub fn del(conn: &mut KV, key: String) -> bool {
-        let mut db = conn.db.borrow_mut();
-        match db.rem(&key) {
-            Ok(bool) => bool,
-            Err(_) => false,
-        }
+    #[rhai_fn(global, pure, return_raw)]
+    pub fn incr(conn: &mut KV, key: String) -> Result<INT, Box<EvalAltResult>> { incr_by(conn, key, 1) }
+
+    /// Adds `by` to the integer under `key` atomically, starting from 0 and keeping its ttl.
+    #[rhai_fn(global, pure, return_raw, name = "incr")]
+    pub fn incr_by(conn: &mut KV, key: String, by: INT) -> Result<INT, Box<EvalAltResult>> {
+        let result = write(&conn.db, |store| {
+            let current = match store.get(&key)? {
+                Some(value) => match decode(&value).as_int() {
+                    Ok(current) => current,
+                    Err(kind) => return Ok(Err(format!("Cannot incr '{key}', it holds {kind}"))),
+                },
+                None => 0,
+            };
+
+            let Some(next) = current.checked_add(by) else {
+                return Ok(Err(format!("Cannot incr '{key}', the result overflows")));
+            };
+
+            let expires = store.expiry.get(&*key)?.map(|at| at.value());
+            store.put(&key, next.to_string().as_bytes(), expires)?;
+            Ok(Ok(next))
+        });
+
+        result.map_err(script_err)?.map_err(Into::into)
+    }
+
+    /// Replaces the value under `key` with `new` only when it still equals `expected`,
+    /// use `()` as `expected` to only create missing keys. Like `incr`, an existing key keeps its ttl.
+    #[rhai_fn(global, pure, return_raw)]
+    pub fn compare_and_swap(conn: &mut KV, key: String, expected: Dynamic, new: Dynamic) -> Result<bool, Box<EvalAltResult>> {
+        let expected = serde_json::to_value(&expected).map_err(|err| format!("Cannot compare value, {err}"))?;
+        let new = encode(&new)?;
+
+        write(&conn.db, |store| {
+            let (current, expires) = match store.get(&key)? {
+                Some(value) => (serde_json::from_slice(&value).unwrap_or_default(), store.expiry.get(&*key)?.map(|at| at.value())),
+                None => (serde_json::Value::Null, None),
+            };
+
+            if current != expected {
+                return Ok(false);
+            }
+
+            store.put(&key, &new, expires).map(|_| true)
+        })
+        .map_err(script_err)
     }
 
-    #[rhai_fn(global)]
-    pub fn exists(conn: KV, key: String) -> bool { conn.db.borrow().exists(&key) }
+    /// Every entry whose key starts with `prefix`, in key order, as `#{key, value}` maps.
+    #[rhai_fn(global, return_raw)]
+    pub fn scan(conn: KV, prefix: String) -> Result<Array, Box<EvalAltResult>> {
+        let entries = read(&conn.db, |snapshot| snapshot.entries(&prefix)).map_err(script_err)?;
 
-    #[rhai_fn(global)]
-    pub fn list(conn: KV) -> Vec<String> { conn.db.borrow().get_all() }
+        Ok(entries
+            .into_iter()
+            .map(|(key, value)| {
+                let mut entry = Map::new();
+                entry.insert("key".into(), key.into());
+                entry.insert("value".into(), decode(&value));
+                Dynamic::from_map(entry)
+            })
+            .collect())
+    }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn list(conn: KV) -> Result<Array, Box<EvalAltResult>> {
+        let entries = read(&conn.db, |snapshot| snapshot.entries("")).map_err(script_err)?;
+        Ok(entries.into_iter().map(|(key, _)| Dynamic::from(key)).collect())
+    }
 
-    #[rhai_fn(global)]
-    pub fn count(conn: KV) -> i64 { conn.db.borrow().total_keys() as i64 }
+    #[rhai_fn(global, return_raw)]
+    pub fn count(conn: KV) -> Result<i64, Box<EvalAltResult>> { read(&conn.db, |snapshot| Ok(snapshot.entries("")?.len() as i64)).map_err(script_err) }
 
+    /// Stores stay open for the life of the server, this only releases the handle.
     #[rhai_fn(global, name = "drop")]
-    pub fn drop_db(conn: KV) { drop(conn.db.borrow()); }
+    pub fn drop_db(conn: KV) { drop(conn) }
 }
@@ -0,0 +1,291 @@
+use crate::{
+    database::pool,
+    http,
+    structs::{
+        config::{Config, MigrationsConfig},
+        modules::SqlTransaction,
+    },
+};
+
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
+use rhai::Dynamic;
+use rusqlite::{params, Connection};
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const TABLE: &str = "CREATE TABLE IF NOT EXISTS script_migrations (version INTEGER PRIMARY KEY, name TEXT NOT NULL, applied_at INTEGER NOT NULL)";
+
+#[derive(Clone, Debug)]
+pub enum Kind {
+    Sql { up: PathBuf, down: Option<PathBuf> },
+    Script(PathBuf),
+}
+
+#[derive(Clone, Debug)]
+pub struct Migration {
+    pub version: u64,
+    pub name: String,
+    pub kind: Kind,
+}
+
+/// A migration found on disk, in the table, or both.
+pub struct Status {
+    pub version: u64,
+    pub name: String,
+    /// Unix milliseconds, `None` while pending.
+    pub applied_at: Option<i64>,
+    /// False when the migration was applied but its file has since been removed.
+    pub exists: bool,
+}
+
+/// The files sharing one version while scanning the directory.
+#[derive(Default)]
+struct Files {
+    name: String,
+    up: Option<PathBuf>,
+    down: Option<PathBuf>,
+    script: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Direction {
+    Up,
+    Down,
+}
+
+impl fmt::Display for Migration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "{:04}_{}", self.version, self.name) }
+}
+
+fn settings(config: &Config) -> MigrationsConfig { config.database.as_ref().and_then(|database| database.migrations.to_owned()).unwrap_or_default() }
+
+fn connection(pool: &Pool<SqliteConnectionManager>) -> Result<PooledConnection<SqliteConnectionManager>, String> { pool.get().map_err(|err| format!("Cannot connect to sqlite, {err}")) }
+
+fn now() -> i64 { SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis() as i64).unwrap_or_default() }
+
+/// Splits `0001_create_users.up.sql` into its version, name and suffix.
+fn parse_name(file: &str) -> Option<(u64, &str, &str)> {
+    let (stem, suffix) = [".up.sql", ".down.sql", ".rt"].iter().find_map(|suffix| file.strip_suffix(suffix).map(|stem| (stem, *suffix)))?;
+    let (version, name) = stem.split_once('_')?;
+
+    match version.chars().all(|c| c.is_ascii_digit()) && !name.is_empty() {
+        true => Some((version.parse().ok()?, name, suffix)),
+        false => None,
+    }
+}
+
+/// Every migration in `dir` ordered by version, a missing directory has none.
+pub fn discover(dir: &Path) -> Result<Vec<Migration>, String> {
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let entries = fs::read_dir(dir).map_err(|err| format!("Cannot read {}, {err}", dir.display()))?;
+    let mut found: BTreeMap<u64, Files> = BTreeMap::new();
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let file = entry.file_name().to_string_lossy().to_string();
+
+        let Some((version, name, suffix)) = parse_name(&file) else {
+            if file.ends_with(".sql") || file.ends_with(".rt") {
+                return Err(format!("{} should be named like 0001_name.up.sql, 0001_name.down.sql or 0001_name.rt", path.display()));
+            }
+            continue;
+        };
+
+        let files = found.entry(version).or_insert_with(|| Files { name: name.to_string(), ..Default::default() });
+
+        if files.name != name {
+            return Err(format!("version {version} is used by both {} and {name}", files.name));
+        }
+
+        match suffix {
+            ".up.sql" => files.up = Some(path),
+            ".down.sql" => files.down = Some(path),
+            _ => files.script = Some(path),
+        }
+    }
+
+    found
+        .into_iter()
+        .map(|(version, Files { name, up, down, script })| {
+            let kind = match (up, down, script) {
+                (None, None, Some(script)) => Kind::Script(script),
+                (Some(up), down, None) => Kind::Sql { up, down },
+                (_, _, Some(_)) => return Err(format!("{version:04}_{name} has both .sql and .rt files, keep one")),
+                (None, Some(_), None) => return Err(format!("{version:04}_{name}.down.sql has no matching .up.sql")),
+                (None, None, None) => unreachable!(),
+            };
+            Ok(Migration { version, name, kind })
+        })
+        .collect()
+}
+
+fn applied(conn: &Connection) -> Result<BTreeMap<u64, (String, i64)>, String> {
+    conn.execute_batch(TABLE).map_err(|err| format!("Cannot create the migrations table, {err}"))?;
+
+    let mut stmt = conn.prepare("SELECT version, name, applied_at FROM script_migrations").map_err(|err| err.to_string())?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, i64>(0)? as u64, (row.get(1)?, row.get(2)?))))
+        .map_err(|err| err.to_string())?;
+
+    rows.collect::<Result<_, _>>().map_err(|err| err.to_string())
+}
+
+fn record(conn: &Connection, migration: &Migration, direction: Direction) -> rusqlite::Result<usize> {
+    match direction {
+        Direction::Up => conn.execute("INSERT INTO script_migrations (version, name, applied_at) VALUES (?1, ?2, ?3)", params![migration.version as i64, migration.name, now()]),
+        Direction::Down => conn.execute("DELETE FROM script_migrations WHERE version = ?1", params![migration.version as i64]),
+    }
+}
+
+fn run_sql(pool: &Pool<SqliteConnectionManager>, migration: &Migration, path: &Path, direction: Direction) -> Result<(), String> {
+    let sql = fs::read_to_string(path).map_err(|err| format!("Cannot read {}, {err}", path.display()))?;
+    let mut conn = connection(pool)?;
+
+    let tx = conn.transaction().map_err(|err| err.to_string())?;
+    tx.execute_batch(&sql).map_err(|err| err.to_string())?;
+    record(&tx, migration, direction).map_err(|err| err.to_string())?;
+    tx.commit().map_err(|err| err.to_string())
+}
+
+/// Calls `fn up` or `fn down`, inside a transaction passed as `tx` when the function takes one.
+/// Without it the script runs like a route and is recorded once it returns.
+fn run_script(config: &Config, pool: &Pool<SqliteConnectionManager>, migration: &Migration, path: &Path, direction: Direction) -> Result<(), String> {
+    let engine = http::engine(config);
+    let source = fs::read_to_string(path).map_err(|err| format!("Cannot read {}, {err}", path.display()))?;
+    let ast = engine.compile(source).map_err(|err| format!("{} does not compile, {err}", path.display()))?;
+
+    let name = match direction {
+        Direction::Up => "up",
+        Direction::Down => "down",
+    };
+
+    let arity = match ast.iter_functions().find(|function| function.name == name) {
+        Some(function) => function.params.len(),
+        None => return Err(format!("{} has no fn {name}()", path.display())),
+    };
+
+    match arity {
+        0 => {
+            let _ = engine.call_fn::<Dynamic>(&mut http::globals(), &ast, name, ()).map_err(|err| err.to_string())?;
+            record(&*connection(pool)?, migration, direction).map(|_| ()).map_err(|err| err.to_string())
+        }
+        1 => {
+            let conn = connection(pool)?;
+            conn.execute_batch("BEGIN IMMEDIATE").map_err(|err| err.to_string())?;
+
+            let tx = SqlTransaction { conn: Arc::new(Mutex::new(Some(conn))) };
+            let result = engine.call_fn::<Dynamic>(&mut http::globals(), &ast, name, (tx.clone(),)).map_err(|err| err.to_string());
+
+            let Some(conn) = tx.conn.lock().ok().and_then(|mut conn| conn.take()) else {
+                return Err("Transaction connection was lost".into());
+            };
+
+            match result.and_then(|_| record(&conn, migration, direction).map_err(|err| err.to_string())) {
+                Ok(_) => conn.execute_batch("COMMIT").map_err(|err| err.to_string()),
+                Err(err) => {
+                    if let Err(rollback) = conn.execute_batch("ROLLBACK") {
+                        log::warn!(err = rollback.to_string(), "Cannot roll back migration");
+                    }
+                    Err(err)
+                }
+            }
+        }
+        _ => Err(format!("fn {name} in {} takes at most one parameter, the transaction", path.display())),
+    }
+}
+
+fn run(config: &Config, pool: &Pool<SqliteConnectionManager>, migration: &Migration, direction: Direction) -> Result<(), String> {
+    let result = match (&migration.kind, direction) {
+        (Kind::Sql { up, .. }, Direction::Up) => run_sql(pool, migration, up, direction),
+        (Kind::Sql { down: Some(down), .. }, Direction::Down) => run_sql(pool, migration, down, direction),
+        (Kind::Sql { down: None, .. }, Direction::Down) => Err(format!("there is no {migration}.down.sql")),
+        (Kind::Script(path), _) => run_script(config, pool, migration, path, direction),
+    };
+
+    result.map_err(|err| format!("{migration} failed, {err}"))
+}
+
+fn open(config: &Config) -> Result<(MigrationsConfig, Pool<SqliteConnectionManager>), String> {
+    let settings = settings(config);
+    let pool = pool::sqlite(settings.connection.as_deref())?;
+    Ok((settings, pool))
+}
+
+/// Applies every pending migration in order, stopping at the first failure.
+pub fn migrate(config: &Config, mut applied_one: impl FnMut(&Migration)) -> Result<usize, String> {
+    let (settings, pool) = open(config)?;
+    let migrations = discover(&settings.dir())?;
+    let done = applied(&*connection(&pool)?)?;
+    let mut count = 0;
+
+    for migration in migrations.iter().filter(|migration| !done.contains_key(&migration.version)) {
+        run(config, &pool, migration, Direction::Up)?;
+        applied_one(migration);
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Reverts the last `steps` applied migrations, newest first.
+pub fn rollback(config: &Config, steps: usize, mut reverted_one: impl FnMut(&Migration)) -> Result<usize, String> {
+    let (settings, pool) = open(config)?;
+    let migrations: BTreeMap<u64, Migration> = discover(&settings.dir())?.into_iter().map(|migration| (migration.version, migration)).collect();
+    let done = applied(&*connection(&pool)?)?;
+    let mut count = 0;
+
+    for (version, (name, _)) in done.iter().rev().take(steps) {
+        let Some(migration) = migrations.get(version) else {
+            return Err(format!("{version:04}_{name} is applied but its file is missing from {}", settings.dir().display()));
+        };
+
+        run(config, &pool, migration, Direction::Down)?;
+        reverted_one(migration);
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Applied and pending migrations ordered by version.
+pub fn status(config: &Config) -> Result<Vec<Status>, String> {
+    let (settings, pool) = open(config)?;
+    let mut done = applied(&*connection(&pool)?)?;
+
+    let mut list: Vec<Status> = discover(&settings.dir())?
+        .into_iter()
+        .map(|migration| Status {
+            applied_at: done.remove(&migration.version).map(|(_, at)| at),
+            version: migration.version,
+            name: migration.name,
+            exists: true,
+        })
+        .collect();
+
+    list.extend(done.into_iter().map(|(version, (name, at))| Status { version, name, applied_at: Some(at), exists: false }));
+    list.sort_by_key(|status| status.version);
+
+    Ok(list)
+}
+
+/// Applies pending migrations before the server starts when `on_start` is set.
+pub fn on_start(config: &Config) -> Result<(), String> {
+    if !settings(config).on_start {
+        return Ok(());
+    }
+
+    let count = migrate(config, |migration| log::info!(migration = migration.to_string(), "applied migration"))?;
+    log::info!(count, "migrations up to date");
+
+    Ok(())
+}
@@ -1,61 +1,293 @@
-use crate::{helpers::collection_exists, prelude::*, structs::config::Config, structs::modules::*};
-use rhai::{plugin::*, serde::to_dynamic, Array, FnNamespace};
-use std::sync::Arc;
+use crate::{database::pool, helpers::collection_exists, structs::modules::*};
+use rhai::{plugin::*, serde::to_dynamic, Array, Blob, FnNamespace, Map, FLOAT, INT};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use mongodb::{
-    bson::{doc, Document},
-    results::{CollectionSpecification, DeleteResult, InsertOneResult, UpdateResult},
-    sync::{Client as MongoClient, Collection, Cursor},
+    bson::{oid::ObjectId, spec::BinarySubtype, Binary, Bson, DateTime, Document},
+    options::{AggregateOptions, CountOptions, FindOneOptions, FindOptions, IndexOptions, ReplaceOptions, UpdateModifications, UpdateOptions},
+    results::{CollectionSpecification, DeleteResult, UpdateResult},
+    sync::Collection,
+    IndexModel,
 };
 
-#[export_module]
-pub mod mongo_db {
-    trait MongoVec {
-        fn into_vec(self) -> Vec<MongoDynamic>;
+/// Converts a script value to bson, `path` names the offending field in errors.
+fn to_bson(value: &Dynamic, path: &str) -> Result<Bson, String> {
+    if value.is_unit() {
+        return Ok(Bson::Null);
     }
 
-    trait MongoDocument {
-        fn into_doc(self) -> Document;
-        fn into_map(self) -> MongoDynamic;
+    if let Some(map) = value.read_lock::<Map>() {
+        let mut document = Document::new();
+        for (key, value) in map.iter() {
+            let path = match path.is_empty() {
+                true => key.to_string(),
+                false => format!("{path}.{key}"),
+            };
+            document.insert(key.as_str(), to_bson(value, &path)?);
+        }
+        return Ok(Bson::Document(document));
     }
 
-    impl Into<MongoDynamic> for Dynamic {
-        fn into(self) -> MongoDynamic { MongoDynamic(self) }
+    if let Some(items) = value.read_lock::<Array>() {
+        let items = items.iter().enumerate().map(|(index, item)| to_bson(item, &format!("{path}[{index}]")));
+        return Ok(Bson::Array(items.collect::<Result<_, _>>()?));
     }
 
-    impl MongoVec for Array {
-        fn into_vec(self) -> Vec<MongoDynamic> { self.into_iter().map(|m| m.into_map()).collect() }
+    if let Some(bytes) = value.read_lock::<Blob>() {
+        let bytes = bytes.to_vec();
+        return Ok(Bson::Binary(Binary { subtype: BinarySubtype::Generic, bytes }));
     }
 
-    impl FromIterator<MongoDynamic> for Array {
-        fn from_iter<I: IntoIterator<Item = MongoDynamic>>(iter: I) -> Self { iter.into_iter().map(|m| m.0).collect() }
+    if let Ok(int) = value.as_int() {
+        return Ok(Bson::Int64(int));
+    }
+    if let Ok(float) = value.as_float() {
+        return Ok(Bson::Double(float));
+    }
+    if let Ok(bool) = value.as_bool() {
+        return Ok(Bson::Boolean(bool));
+    }
+    if let Ok(char) = value.as_char() {
+        return Ok(Bson::String(char.to_string()));
+    }
+    if let Some(string) = value.read_lock::<ImmutableString>() {
+        return Ok(Bson::String(string.to_string()));
     }
 
-    impl MongoDocument for Dynamic {
-        fn into_doc(self) -> Document {
-            Document::from(
-                serde_json::from_str(&match serde_json::to_string(&self) {
-                    Ok(data) => data,
-                    Err(err) => format!("{{\"err\": \"{err}\"}}"),
-                })
-                .unwrap_or(doc! {"err": format!("unable to deserialize {self}")}),
-            )
+    if let Some(oid) = value.read_lock::<ObjectId>() {
+        return Ok(Bson::ObjectId(*oid));
+    }
+    if let Some(date) = value.read_lock::<DateTime>() {
+        return Ok(Bson::DateTime(*date));
+    }
+    if let Some(bson) = value.read_lock::<Bson>() {
+        return Ok(bson.clone());
+    }
+
+    let kind = value.type_name().rsplit("::").next().unwrap_or_default();
+
+    match path.is_empty() {
+        true => Err(format!("Cannot store a {kind} in mongo")),
+        false => Err(format!("Cannot store '{path}' in mongo, {kind} is not supported")),
+    }
+}
+
+fn to_document(value: &Dynamic) -> Result<Document, Box<EvalAltResult>> {
+    match to_bson(value, "")? {
+        Bson::Document(document) => Ok(document),
+        _ => Err(format!("Expected a map, found {}", value.type_name()).into()),
+    }
+}
+
+/// Converts bson to a script value, ObjectIds and dates stay typed so they can be written back.
+fn from_bson(bson: Bson) -> Dynamic {
+    match bson {
+        Bson::Null | Bson::Undefined => Dynamic::UNIT,
+        Bson::Boolean(bool) => bool.into(),
+        Bson::Int32(int) => (int as INT).into(),
+        Bson::Int64(int) => int.into(),
+        Bson::Double(float) => (float as FLOAT).into(),
+        Bson::String(string) => string.into(),
+        Bson::Array(items) => items.into_iter().map(from_bson).collect::<Array>().into(),
+        Bson::Document(document) => Dynamic::from_map(from_document(document)),
+        Bson::Binary(binary) => Dynamic::from_blob(binary.bytes),
+        Bson::ObjectId(oid) => Dynamic::from(oid),
+        Bson::DateTime(date) => Dynamic::from(date),
+        // kept opaque so rarer types such as decimals and timestamps survive a round trip
+        other => Dynamic::from(other),
+    }
+}
+
+fn from_document(document: Document) -> Map { document.into_iter().map(|(key, value)| (key.into(), from_bson(value))).collect() }
+
+/// Replaces ObjectIds and dates with strings, so values serialize to readable json.
+pub fn plain(value: Dynamic) -> Dynamic {
+    if value.is_map() {
+        let map = value.cast::<Map>();
+        return Dynamic::from_map(map.into_iter().map(|(key, value)| (key, plain(value))).collect());
+    }
+
+    if value.is_array() {
+        return value.cast::<Array>().into_iter().map(plain).collect::<Array>().into();
+    }
+
+    if let Some(oid) = value.read_lock::<ObjectId>() {
+        return oid.to_hex().into();
+    }
+    if let Some(date) = value.read_lock::<DateTime>() {
+        return date.try_to_rfc3339_string().unwrap_or_else(|_| date.to_string()).into();
+    }
+    if let Some(bson) = value.read_lock::<Bson>() {
+        return to_dynamic(bson.clone().into_relaxed_extjson()).unwrap_or_default();
+    }
+
+    value
+}
+
+/// Builds a document whose key order matters, such as a sort or index keys. Maps are ordered
+/// alphabetically, so an array of maps is merged in order to control which key comes first.
+fn ordered(value: &Dynamic, name: &str) -> Result<Document, Box<EvalAltResult>> {
+    match value.read_lock::<Array>() {
+        Some(items) => {
+            let mut document = Document::new();
+            for item in items.iter() {
+                document.extend(to_document(item).map_err(|_| format!("'{name}' must be a map or an array of maps"))?);
+            }
+            Ok(document)
+        }
+        None => to_document(value),
+    }
+}
+
+/// Reads the update from a map of `$` operators or a pipeline, `None` for a plain replacement.
+fn modifications(update: &Dynamic) -> Result<Option<UpdateModifications>, Box<EvalAltResult>> {
+    if let Some(stages) = update.read_lock::<Array>() {
+        let stages = stages.iter().map(to_document).collect::<Result<Vec<Document>, _>>()?;
+        return Ok(Some(UpdateModifications::Pipeline(stages)));
+    }
+
+    let document = to_document(update)?;
+
+    match document.keys().next().is_some_and(|key| key.starts_with('$')) {
+        true => Ok(Some(UpdateModifications::Document(document))),
+        false => Ok(None),
+    }
+}
+
+fn update_result(res: UpdateResult) -> Map {
+    let mut result = Map::new();
+    result.insert("matchedCount".into(), (res.matched_count as INT).into());
+    result.insert("modifiedCount".into(), (res.modified_count as INT).into());
+    result.insert("upsertedId".into(), res.upserted_id.map(from_bson).unwrap_or_default());
+    result
+}
+
+/// An option map for one call, unknown keys are rejected so typos don't go unnoticed.
+struct Options {
+    call: &'static str,
+    map: Map,
+}
+
+impl Options {
+    fn new(call: &'static str, map: Map, keys: &[&str]) -> Result<Self, Box<EvalAltResult>> {
+        if let Some(key) = map.keys().find(|key| !keys.contains(&key.as_str())) {
+            return Err(format!("Unknown {call} option '{key}', expected one of {}", keys.join(", ")).into());
+        }
+        Ok(Self { call, map })
+    }
+
+    fn invalid(&self, key: &str, kind: &str) -> Box<EvalAltResult> { format!("{} option '{key}' must be {kind}", self.call).into() }
+
+    fn int(&self, key: &str) -> Result<Option<INT>, Box<EvalAltResult>> {
+        match self.map.get(key) {
+            Some(value) => value.as_int().map(Some).map_err(|_| self.invalid(key, "an int")),
+            None => Ok(None),
+        }
+    }
+
+    fn unsigned(&self, key: &str) -> Result<Option<u64>, Box<EvalAltResult>> {
+        match self.int(key)? {
+            Some(value) => u64::try_from(value).map(Some).map_err(|_| self.invalid(key, "zero or more")),
+            None => Ok(None),
         }
-        fn into_map(self) -> MongoDynamic { MongoDynamic(to_dynamic(self.into_doc()).unwrap()) }
     }
 
-    pub fn connect() -> Client {
-        // add error handling
-        // .get_db(Database::Mongo)
-        let config = Config::new().set_path(&crate::Cli::parse().config).read().database.unwrap();
+    fn bool(&self, key: &str) -> Result<Option<bool>, Box<EvalAltResult>> {
+        match self.map.get(key) {
+            Some(value) => value.as_bool().map(Some).map_err(|_| self.invalid(key, "a bool")),
+            None => Ok(None),
+        }
+    }
 
-        match MongoClient::with_uri_str(config.mongo.unwrap().server.unwrap_or("".to_string())) {
-            Ok(client) => Client { client: Some(client) },
-            Err(_) => Client { client: None },
+    fn string(&self, key: &str) -> Result<Option<String>, Box<EvalAltResult>> {
+        match self.map.get(key) {
+            Some(value) => value.clone().into_string().map(Some).map_err(|_| self.invalid(key, "a string")),
+            None => Ok(None),
         }
     }
 
-    pub fn shutdown(conn: Client) { conn.client.unwrap().shutdown(); }
+    fn document(&self, key: &str) -> Result<Option<Document>, Box<EvalAltResult>> {
+        match self.map.get(key) {
+            Some(value) => to_document(value).map(Some).map_err(|_| self.invalid(key, "a map")),
+            None => Ok(None),
+        }
+    }
+
+    fn ordered(&self, key: &str) -> Result<Option<Document>, Box<EvalAltResult>> { self.map.get(key).map(|value| ordered(value, key)).transpose() }
+}
+
+impl Iterator for MongoCursor {
+    type Item = Result<Map, Box<EvalAltResult>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut cursor = self.cursor.lock().ok()?;
+        cursor.next().map(|item| item.map(from_document).map_err(|err| err.to_string().into()))
+    }
+}
+
+#[export_module]
+pub mod mongo_db {
+    #[rhai_fn(return_raw)]
+    pub fn connect() -> Result<Client, Box<EvalAltResult>> {
+        match pool::mongo(None) {
+            Ok(client) => Ok(Client { client: Some(client) }),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    #[rhai_fn(return_raw, name = "connect")]
+    pub fn connect_named(name: &str) -> Result<Client, Box<EvalAltResult>> {
+        match pool::mongo(Some(name)) {
+            Ok(client) => Ok(Client { client: Some(client) }),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// The client is shared by every request and closes when the server stops.
+    pub fn shutdown(_conn: Client) {}
+
+    /// A new ObjectId.
+    #[rhai_fn(name = "oid")]
+    pub fn new_oid() -> ObjectId { ObjectId::new() }
+
+    #[rhai_fn(return_raw, name = "oid")]
+    pub fn parse_oid(hex: &str) -> Result<ObjectId, Box<EvalAltResult>> { ObjectId::parse_str(hex).map_err(|err| format!("Invalid ObjectId '{hex}', {err}").into()) }
+
+    /// The current time.
+    #[rhai_fn(name = "date")]
+    pub fn now() -> DateTime { DateTime::now() }
+
+    /// A date from unix milliseconds.
+    #[rhai_fn(name = "date")]
+    pub fn date_millis(millis: INT) -> DateTime { DateTime::from_millis(millis) }
+
+    /// A date from an RFC 3339 string such as `2024-01-31T12:00:00Z`.
+    #[rhai_fn(return_raw, name = "date")]
+    pub fn date_parse(date: &str) -> Result<DateTime, Box<EvalAltResult>> { DateTime::parse_rfc3339_str(date).map_err(|err| format!("Invalid date '{date}', {err}").into()) }
+
+    #[rhai_fn(global, name = "to_string", name = "to_debug")]
+    pub fn oid_string(oid: &mut ObjectId) -> String { oid.to_hex() }
+
+    #[rhai_fn(global, name = "==")]
+    pub fn oid_eq(a: ObjectId, b: ObjectId) -> bool { a == b }
+
+    #[rhai_fn(global, name = "!=")]
+    pub fn oid_ne(a: ObjectId, b: ObjectId) -> bool { a != b }
+
+    #[rhai_fn(global, name = "to_string", name = "to_debug")]
+    pub fn date_string(date: &mut DateTime) -> String { date.try_to_rfc3339_string().unwrap_or_else(|_| date.to_string()) }
+
+    #[rhai_fn(global, get = "millis")]
+    pub fn date_millis_get(date: &mut DateTime) -> INT { date.timestamp_millis() }
+
+    #[rhai_fn(global, name = "==")]
+    pub fn date_eq(a: DateTime, b: DateTime) -> bool { a == b }
+
+    #[rhai_fn(global, name = "!=")]
+    pub fn date_ne(a: DateTime, b: DateTime) -> bool { a != b }
 
     #[rhai_fn(global, return_raw, name = "list")]
     pub fn list_databases(conn: Client) -> Result<Dynamic, Box<EvalAltResult>> {
@@ -92,14 +324,17 @@ pub mod mongo_db {
         match m.db {
             Some(client) => match client.list_collections(None, None) {
                 Err(err) => Err(err.to_string().into()),
-                Ok(list) => to_dynamic(list.map(|item| item.unwrap()).collect::<Vec<CollectionSpecification>>()),
+                Ok(list) => match list.collect::<Result<Vec<CollectionSpecification>, _>>() {
+                    Ok(list) => to_dynamic(list),
+                    Err(err) => Err(err.to_string().into()),
+                },
             },
             None => to_dynamic::<Array>(vec![]),
         }
     }
 
     #[rhai_fn(global, return_raw, name = "get")]
-    pub fn collection(m: Mongo, name: String) -> Result<Collection<MongoDynamic>, Box<EvalAltResult>> {
+    pub fn collection(m: Mongo, name: String) -> Result<Collection<Document>, Box<EvalAltResult>> {
         match m.db {
             Some(client) => Ok(client.collection(&name)),
             None => Err("No database found".into()),
@@ -107,9 +342,9 @@ pub mod mongo_db {
     }
 
     #[rhai_fn(global, return_raw, name = "create")]
-    pub fn create_collection(m: Mongo, name: String) -> Result<Collection<MongoDynamic>, Box<EvalAltResult>> {
+    pub fn create_collection(m: Mongo, name: String) -> Result<Collection<Document>, Box<EvalAltResult>> {
         match m.db {
-            Some(client) => match collection_exists(&client, &name).unwrap() {
+            Some(client) => match collection_exists(&client, &name)? {
                 true => Ok(client.collection(&name)),
                 false => match client.create_collection(&name, None) {
                     Err(err) => Err(err.to_string().into()),
@@ -121,67 +356,138 @@ pub mod mongo_db {
     }
 
     #[rhai_fn(global, return_raw, name = "count")]
-    pub fn count_collections(collection: Collection<MongoDynamic>) -> Result<i64, Box<EvalAltResult>> {
+    pub fn count_collections(collection: Collection<Document>) -> Result<i64, Box<EvalAltResult>> {
         match collection.count_documents(None, None) {
             Ok(count) => Ok(count as i64),
             Err(err) => Err(err.to_string().into()),
         }
     }
 
+    #[rhai_fn(global, return_raw, name = "count_documents")]
+    pub fn count_documents(collection: Collection<Document>, filter: Dynamic) -> Result<i64, Box<EvalAltResult>> { count_documents_options(collection, filter, Map::new()) }
+
+    #[rhai_fn(global, return_raw, name = "count_documents")]
+    pub fn count_documents_options(collection: Collection<Document>, filter: Dynamic, options: Map) -> Result<i64, Box<EvalAltResult>> {
+        let options = Options::new("count_documents", options, &["limit", "skip"])?;
+        let options = CountOptions::builder().limit(options.unsigned("limit")?).skip(options.unsigned("skip")?).build();
+
+        match collection.count_documents(to_document(&filter)?, options) {
+            Ok(count) => Ok(count as i64),
+            Err(err) => Err(err.to_string().into()),
+        }
+    }
+
+    #[rhai_fn(global, return_raw, name = "distinct")]
+    pub fn distinct(collection: Collection<Document>, field: &str) -> Result<Array, Box<EvalAltResult>> { distinct_filter(collection, field, Dynamic::from_map(Map::new())) }
+
+    #[rhai_fn(global, return_raw, name = "distinct")]
+    pub fn distinct_filter(collection: Collection<Document>, field: &str, filter: Dynamic) -> Result<Array, Box<EvalAltResult>> {
+        match collection.distinct(field, to_document(&filter)?, None) {
+            Ok(values) => Ok(values.into_iter().map(from_bson).collect()),
+            Err(err) => Err(err.to_string().into()),
+        }
+    }
+
     #[rhai_fn(global, return_raw, name = "find")]
-    pub fn find_all(collection: Collection<MongoDynamic>) -> Result<Arc<Cursor<MongoDynamic>>, Box<EvalAltResult>> {
+    pub fn find_all(collection: Collection<Document>) -> Result<MongoCursor, Box<EvalAltResult>> {
         match collection.find(None, None) {
-            Ok(cursor) => Ok(Arc::new(cursor)),
+            Ok(cursor) => Ok(MongoCursor { cursor: Arc::new(Mutex::new(cursor)) }),
+            Err(err) => Err(err.to_string().into()),
+        }
+    }
+
+    #[rhai_fn(global, return_raw, name = "find")]
+    pub fn find_filter(collection: Collection<Document>, filter: Dynamic) -> Result<MongoCursor, Box<EvalAltResult>> { find_options(collection, filter, Map::new()) }
+
+    #[rhai_fn(global, return_raw, name = "find")]
+    pub fn find_options(collection: Collection<Document>, filter: Dynamic, options: Map) -> Result<MongoCursor, Box<EvalAltResult>> {
+        let options = Options::new("find", options, &["sort", "limit", "skip", "projection", "batch_size"])?;
+        let options = FindOptions::builder()
+            .sort(options.ordered("sort")?)
+            .limit(options.int("limit")?)
+            .skip(options.unsigned("skip")?)
+            .projection(options.document("projection")?)
+            .batch_size(options.unsigned("batch_size")?.map(|size| size as u32))
+            .build();
+
+        match collection.find(to_document(&filter)?, options) {
+            Ok(cursor) => Ok(MongoCursor { cursor: Arc::new(Mutex::new(cursor)) }),
             Err(err) => Err(err.to_string().into()),
         }
     }
 
     #[rhai_fn(global, return_raw, name = "find_one")]
-    pub fn find_one(collection: Collection<MongoDynamic>, filter: Dynamic) -> Result<Dynamic, Box<EvalAltResult>> {
-        match collection.find_one(filter.into_doc(), None) {
+    pub fn find_one(collection: Collection<Document>, filter: Dynamic) -> Result<Dynamic, Box<EvalAltResult>> { find_one_options(collection, filter, Map::new()) }
+
+    #[rhai_fn(global, return_raw, name = "find_one")]
+    pub fn find_one_options(collection: Collection<Document>, filter: Dynamic, options: Map) -> Result<Dynamic, Box<EvalAltResult>> {
+        let options = Options::new("find_one", options, &["sort", "skip", "projection"])?;
+        let options = FindOneOptions::builder()
+            .sort(options.ordered("sort")?)
+            .skip(options.unsigned("skip")?)
+            .projection(options.document("projection")?)
+            .build();
+
+        match collection.find_one(to_document(&filter)?, options) {
             Ok(cursor) => match cursor {
-                Some(item) => to_dynamic::<MongoDynamic>(item),
-                None => to_dynamic::<Vec<MongoDynamic>>(vec![]),
+                Some(item) => Ok(Dynamic::from_map(from_document(item))),
+                None => Ok(Array::new().into()),
             },
             Err(err) => Err(err.to_string().into()),
         }
     }
 
-    #[rhai_fn(global, return_raw, name = "find")]
-    pub fn find_filter(collection: Collection<MongoDynamic>, filter: Dynamic) -> Result<Arc<Cursor<MongoDynamic>>, Box<EvalAltResult>> {
-        match collection.find(filter.into_doc(), None) {
-            Ok(cursor) => Ok(Arc::new(cursor)),
+    #[rhai_fn(global, return_raw, name = "aggregate")]
+    pub fn aggregate(collection: Collection<Document>, pipeline: Array) -> Result<MongoCursor, Box<EvalAltResult>> { aggregate_options(collection, pipeline, Map::new()) }
+
+    #[rhai_fn(global, return_raw, name = "aggregate")]
+    pub fn aggregate_options(collection: Collection<Document>, pipeline: Array, options: Map) -> Result<MongoCursor, Box<EvalAltResult>> {
+        let options = Options::new("aggregate", options, &["allow_disk_use", "batch_size"])?;
+        let options = AggregateOptions::builder()
+            .allow_disk_use(options.bool("allow_disk_use")?)
+            .batch_size(options.unsigned("batch_size")?.map(|size| size as u32))
+            .build();
+
+        let stages = pipeline.iter().map(to_document).collect::<Result<Vec<Document>, _>>()?;
+
+        match collection.aggregate(stages, options) {
+            Ok(cursor) => Ok(MongoCursor { cursor: Arc::new(Mutex::new(cursor)) }),
             Err(err) => Err(err.to_string().into()),
         }
     }
 
-    #[rhai_fn(global, name = "count")]
-    pub fn count_cursor(cursor: Arc<Cursor<MongoDynamic>>) -> i64 {
-        match Arc::into_inner(cursor) {
-            Some(cursor) => cursor.count() as i64,
-            None => 0,
+    /// The next document, or () once the cursor is exhausted.
+    #[rhai_fn(global, return_raw, name = "next")]
+    pub fn next_document(cursor: &mut MongoCursor) -> Result<Dynamic, Box<EvalAltResult>> {
+        match cursor.next() {
+            Some(item) => item.map(Dynamic::from_map),
+            None => Ok(Dynamic::UNIT),
         }
     }
 
+    /// Counts the documents left in the cursor, reading through them.
+    #[rhai_fn(global, return_raw, name = "count")]
+    pub fn count_cursor(cursor: MongoCursor) -> Result<i64, Box<EvalAltResult>> {
+        let mut count = 0;
+        for item in cursor {
+            item?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
     #[rhai_fn(global, name = "count")]
     pub fn count_collect(items: Array) -> i64 { items.iter().count() as i64 }
 
+    /// Reads the documents left in the cursor into an array.
     #[rhai_fn(global, return_raw, name = "collect")]
-    pub fn collect(cursor: Arc<Cursor<MongoDynamic>>) -> Result<Dynamic, Box<EvalAltResult>> {
-        match Arc::into_inner(cursor) {
-            Some(cursor) => match cursor.collect() {
-                Ok(items) => to_dynamic::<Array>(items),
-                Err(err) => Err(err.to_string().into()),
-            },
-            None => to_dynamic::<Array>(vec![]),
-        }
-    }
+    pub fn collect(cursor: MongoCursor) -> Result<Array, Box<EvalAltResult>> { cursor.map(|item| item.map(Dynamic::from_map)).collect() }
 
-    #[rhai_fn(global, name = "drop")]
-    pub fn drop_collection(collection: Collection<MongoDynamic>) -> bool {
+    #[rhai_fn(global, return_raw, name = "drop")]
+    pub fn drop_collection(collection: Collection<Document>) -> Result<bool, Box<EvalAltResult>> {
         match collection.drop(None) {
-            Ok(_) => true,
-            Err(_) => false,
+            Ok(_) => Ok(true),
+            Err(err) => Err(err.to_string().into()),
         }
     }
 
@@ -197,42 +503,123 @@ pub mod mongo_db {
     }
 
     #[rhai_fn(global, return_raw, name = "insert")]
-    pub fn insert_one(collection: Collection<MongoDynamic>, map: Dynamic) -> Result<Dynamic, Box<EvalAltResult>> {
-        match collection.insert_one(map.into_map(), None) {
-            Ok(res) => to_dynamic::<InsertOneResult>(res),
+    pub fn insert_one(collection: Collection<Document>, map: Dynamic) -> Result<Dynamic, Box<EvalAltResult>> {
+        match collection.insert_one(to_document(&map)?, None) {
+            Ok(res) => {
+                let mut result = Map::new();
+                result.insert("insertedId".into(), from_bson(res.inserted_id));
+                Ok(Dynamic::from_map(result))
+            }
             Err(err) => Err(err.to_string().into()),
         }
     }
 
     #[rhai_fn(global, return_raw, name = "insert")]
-    pub fn insert_many(collection: Collection<MongoDynamic>, map: Array) -> Result<Array, Box<EvalAltResult>> {
-        match collection.insert_many(map.into_vec(), None) {
-            Ok(res) => Ok(res.inserted_ids.into_iter().map(|(_, value)| to_dynamic(value).unwrap()).collect::<Array>()),
+    pub fn insert_many(collection: Collection<Document>, map: Array) -> Result<Array, Box<EvalAltResult>> {
+        let documents = map.iter().map(to_document).collect::<Result<Vec<Document>, _>>()?;
+
+        match collection.insert_many(documents, None) {
+            Ok(res) => {
+                let mut ids: Vec<(usize, Bson)> = res.inserted_ids.into_iter().collect();
+                ids.sort_by_key(|(index, _)| *index);
+                Ok(ids.into_iter().map(|(_, id)| from_bson(id)).collect())
+            }
             Err(err) => Err(err.to_string().into()),
         }
     }
 
     #[rhai_fn(global, return_raw)]
-    pub fn delete(collection: Collection<MongoDynamic>, map: Dynamic) -> Result<Dynamic, Box<EvalAltResult>> {
-        match collection.delete_one(map.into_doc(), None) {
+    pub fn delete(collection: Collection<Document>, map: Dynamic) -> Result<Dynamic, Box<EvalAltResult>> {
+        match collection.delete_one(to_document(&map)?, None) {
             Ok(res) => to_dynamic::<DeleteResult>(res),
             Err(err) => Err(err.to_string().into()),
         }
     }
 
     #[rhai_fn(global, return_raw)]
-    pub fn delete_many(collection: Collection<MongoDynamic>, map: Dynamic) -> Result<Dynamic, Box<EvalAltResult>> {
-        match collection.delete_many(map.into_doc(), None) {
+    pub fn delete_many(collection: Collection<Document>, map: Dynamic) -> Result<Dynamic, Box<EvalAltResult>> {
+        match collection.delete_many(to_document(&map)?, None) {
             Ok(res) => to_dynamic::<DeleteResult>(res),
             Err(err) => Err(err.to_string().into()),
         }
     }
 
-    #[rhai_fn(global, return_raw)]
-    pub fn update(collection: Collection<MongoDynamic>, query: Dynamic, replacement: Dynamic) -> Result<Dynamic, Box<EvalAltResult>> {
-        let replacement: MongoDynamic = replacement.into();
-        match collection.replace_one(query.into_doc(), replacement, None) {
-            Ok(res) => to_dynamic::<UpdateResult>(res),
+    #[rhai_fn(global, return_raw, name = "update")]
+    pub fn update(collection: Collection<Document>, query: Dynamic, update: Dynamic) -> Result<Map, Box<EvalAltResult>> { update_options(collection, query, update, Map::new()) }
+
+    /// Applies `$` operators or a pipeline to the first match, a plain map replaces it.
+    #[rhai_fn(global, return_raw, name = "update")]
+    pub fn update_options(collection: Collection<Document>, query: Dynamic, update: Dynamic, options: Map) -> Result<Map, Box<EvalAltResult>> {
+        let upsert = Options::new("update", options, &["upsert"])?.bool("upsert")?;
+        let query = to_document(&query)?;
+
+        let result = match modifications(&update)? {
+            Some(update) => collection.update_one(query, update, UpdateOptions::builder().upsert(upsert).build()),
+            None => collection.replace_one(query, to_document(&update)?, ReplaceOptions::builder().upsert(upsert).build()),
+        };
+
+        match result {
+            Ok(res) => Ok(update_result(res)),
+            Err(err) => Err(err.to_string().into()),
+        }
+    }
+
+    #[rhai_fn(global, return_raw, name = "update_many")]
+    pub fn update_many(collection: Collection<Document>, query: Dynamic, update: Dynamic) -> Result<Map, Box<EvalAltResult>> {
+        update_many_options(collection, query, update, Map::new())
+    }
+
+    #[rhai_fn(global, return_raw, name = "update_many")]
+    pub fn update_many_options(collection: Collection<Document>, query: Dynamic, update: Dynamic, options: Map) -> Result<Map, Box<EvalAltResult>> {
+        let upsert = Options::new("update_many", options, &["upsert"])?.bool("upsert")?;
+
+        let Some(update) = modifications(&update)? else {
+            return Err("update_many expects update operators such as $set, or a pipeline".into());
+        };
+
+        match collection.update_many(to_document(&query)?, update, UpdateOptions::builder().upsert(upsert).build()) {
+            Ok(res) => Ok(update_result(res)),
+            Err(err) => Err(err.to_string().into()),
+        }
+    }
+
+    #[rhai_fn(global, return_raw, name = "create_index")]
+    pub fn create_index(collection: Collection<Document>, keys: Dynamic) -> Result<String, Box<EvalAltResult>> { create_index_options(collection, keys, Map::new()) }
+
+    /// Creates an index over `keys`, returning its name. Creating an existing index is a no-op.
+    #[rhai_fn(global, return_raw, name = "create_index")]
+    pub fn create_index_options(collection: Collection<Document>, keys: Dynamic, options: Map) -> Result<String, Box<EvalAltResult>> {
+        let options = Options::new("create_index", options, &["name", "unique", "sparse", "expire_after"])?;
+        let index = IndexModel::builder()
+            .keys(ordered(&keys, "keys")?)
+            .options(
+                IndexOptions::builder()
+                    .name(options.string("name")?)
+                    .unique(options.bool("unique")?)
+                    .sparse(options.bool("sparse")?)
+                    .expire_after(options.unsigned("expire_after")?.map(Duration::from_secs))
+                    .build(),
+            )
+            .build();
+
+        match collection.create_index(index, None) {
+            Ok(res) => Ok(res.index_name),
+            Err(err) => Err(err.to_string().into()),
+        }
+    }
+
+    #[rhai_fn(global, return_raw, name = "indexes")]
+    pub fn list_indexes(collection: Collection<Document>) -> Result<Array, Box<EvalAltResult>> {
+        match collection.list_index_names() {
+            Ok(names) => Ok(names.into_iter().map(Dynamic::from).collect()),
+            Err(err) => Err(err.to_string().into()),
+        }
+    }
+
+    #[rhai_fn(global, return_raw, name = "drop_index")]
+    pub fn drop_index(collection: Collection<Document>, name: &str) -> Result<bool, Box<EvalAltResult>> {
+        match collection.drop_index(name, None) {
+            Ok(_) => Ok(true),
             Err(err) => Err(err.to_string().into()),
         }
     }
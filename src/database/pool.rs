@@ -0,0 +1,120 @@
+use crate::{
+    helpers::prelude::*,
+    structs::config::{Config, Connections, MongoConfig, RedisConfig, SqliteConfig},
+};
+
+use macros_rs::fmt::crashln;
+use mongodb::{options::ClientOptions, sync::Client as MongoClient};
+use r2d2::{NopErrorHandler, Pool};
+use r2d2_sqlite::SqliteConnectionManager;
+use redis::Client as RedisClient;
+use std::{collections::BTreeMap, sync::OnceLock, time::Duration};
+
+static REDIS: OnceLock<Connections<(RedisClient, Pool<RedisClient>)>> = OnceLock::new();
+static MONGO: OnceLock<Connections<MongoClient>> = OnceLock::new();
+static SQLITE: OnceLock<Connections<Pool<SqliteConnectionManager>>> = OnceLock::new();
+
+fn redis_pool(config: &RedisConfig) -> Result<(RedisClient, Pool<RedisClient>), String> {
+    let client = RedisClient::open(config.server.as_str()).map_err(|err| err.to_string())?;
+    let mut builder = Pool::builder()
+        .max_size(config.max_pool_size.unwrap_or(10))
+        .min_idle(Some(config.min_pool_size.unwrap_or(0)))
+        .error_handler(Box::new(NopErrorHandler));
+
+    if let Some(timeout) = config.connect_timeout_ms {
+        builder = builder.connection_timeout(Duration::from_millis(timeout));
+    }
+
+    // connections are opened on first use, so a server that is down only fails the scripts using it
+    Ok((client.clone(), builder.build_unchecked(client)))
+}
+
+fn mongo_client(config: &MongoConfig) -> Result<MongoClient, String> {
+    let mut options = match (&config.server, &config.advanced) {
+        (Some(server), _) => ClientOptions::parse(server).map_err(|err| err.to_string())?,
+        (None, Some(advanced)) => advanced.client_options(),
+        (None, None) => return Err("expected server or advanced".into()),
+    };
+
+    options.max_pool_size = config.max_pool_size.or(options.max_pool_size);
+    options.min_pool_size = config.min_pool_size.or(options.min_pool_size);
+
+    MongoClient::with_options(options).map_err(|err| err.to_string())
+}
+
+fn sqlite_pool(config: &SqliteConfig) -> Result<Pool<SqliteConnectionManager>, String> {
+    let timeout = Duration::from_millis(config.busy_timeout_ms.unwrap_or(5000));
+
+    // wal lets readers carry on while another connection writes
+    let manager = SqliteConnectionManager::file(&config.path).with_init(move |conn| {
+        conn.busy_timeout(timeout)?;
+        conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA foreign_keys = ON;")
+    });
+
+    // like redis, the file is only opened once a script uses it
+    Ok(Pool::builder()
+        .max_size(config.max_pool_size.unwrap_or(10))
+        .min_idle(Some(0))
+        .error_handler(Box::new(NopErrorHandler))
+        .build_unchecked(manager))
+}
+
+/// Builds a client for every connection in a section, crashing on the first invalid one.
+fn connect<C, T>(section: &str, connections: &Connections<C>, client: fn(&C) -> Result<T, String>) -> Connections<T> {
+    let mut clients = Connections { default: None, named: BTreeMap::new() };
+
+    for (name, config) in connections.iter() {
+        let created = match client(config) {
+            Ok(created) => created,
+            Err(err) => crashln!("{FAIL} Cannot create the {section}{} client, {err}", name.map(|name| format!(".{name}")).unwrap_or_default()),
+        };
+
+        match name {
+            Some(name) => drop(clients.named.insert(name.to_string(), created)),
+            None => clients.default = Some(created),
+        }
+    }
+
+    clients
+}
+
+/// Creates the shared database clients once, before any script runs.
+pub fn init(config: &Config) {
+    let Some(database) = &config.database else { return };
+
+    if let Some(redis) = &database.redis {
+        drop(REDIS.set(connect("database.redis", redis, redis_pool)));
+    }
+
+    if let Some(mongo) = &database.mongo {
+        drop(MONGO.set(connect("database.mongo", mongo, mongo_client)));
+    }
+
+    if let Some(sqlite) = &database.sqlite {
+        drop(SQLITE.set(connect("database.sqlite", sqlite, sqlite_pool)));
+    }
+}
+
+fn lookup<T: Clone>(clients: &OnceLock<Connections<T>>, section: &str, module: &str, name: Option<&str>) -> Result<T, String> {
+    let Some(clients) = clients.get() else {
+        return Err(format!("{section} is not configured, add a [database.{section}] section"));
+    };
+
+    match (clients.get(name), name) {
+        (Some(client), _) => Ok(client.clone()),
+        (None, Some(name)) => Err(format!("no {section} connection named '{name}', add a [database.{section}.{name}] section")),
+        (None, None) => Err(format!("no default {section} connection, use {module}::connect(name) or configure [database.{section}]")),
+    }
+}
+
+/// The pool for `[database.redis]`, or `[database.redis.<name>]` when named.
+pub fn redis(name: Option<&str>) -> Result<Pool<RedisClient>, String> { lookup(&REDIS, "redis", "redis", name).map(|(_, pool)| pool) }
+
+/// The client behind the redis pool, for connections that must not go back to it such as subscriptions.
+pub fn redis_client(name: Option<&str>) -> Result<RedisClient, String> { lookup(&REDIS, "redis", "redis", name).map(|(client, _)| client) }
+
+/// The client for `[database.mongo]`, or `[database.mongo.<name>]` when named.
+pub fn mongo(name: Option<&str>) -> Result<MongoClient, String> { lookup(&MONGO, "mongo", "mongo", name) }
+
+/// The pool for `[database.sqlite]`, or `[database.sqlite.<name>]` when named.
+pub fn sqlite(name: Option<&str>) -> Result<Pool<SqliteConnectionManager>, String> { lookup(&SQLITE, "sqlite", "sql", name) }
@@ -0,0 +1,79 @@
+use crate::{
+    database::pool,
+    http,
+    routes::parse::{self, Subscription},
+    structs::config::Config,
+};
+
+use redis::{Client as RedisClient, RedisResult};
+use rhai::{Dynamic, Engine, AST};
+use std::{fs, sync::Arc, thread, time::Duration};
+
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Starts a background listener for every `#[subscribe]` handler in the workers.
+pub fn start(config: &Arc<Config>) {
+    for file in &config.workers {
+        // parse errors are reported by `script check` and on requests
+        let Ok(subscriptions) = fs::read_to_string(file).map(|input| parse::subscriptions(&input).unwrap_or_default()) else {
+            continue;
+        };
+
+        for subscription in subscriptions {
+            let config = Arc::clone(config);
+            let name = format!("subscribe {}", subscription.channel);
+
+            if let Err(err) = thread::Builder::new().name(name).spawn(move || listen(config, subscription)) {
+                log::error!(err = err.to_string(), "Cannot start subscription");
+            }
+        }
+    }
+}
+
+fn listen(config: Arc<Config>, subscription: Subscription) {
+    let channel = subscription.channel.to_owned();
+    let client = match pool::redis_client(subscription.cfg.get("connection").map(String::as_str)) {
+        Ok(client) => client,
+        Err(err) => return log::error!(channel, err, "Cannot subscribe"),
+    };
+
+    let engine = http::engine(&config);
+    let ast = match engine.compile(subscription.route.construct_fn()) {
+        Ok(ast) => ast,
+        Err(err) => return log::error!(channel, err = err.to_string(), "Cannot compile subscription handler"),
+    };
+
+    let mut delay = Duration::from_millis(500);
+
+    loop {
+        if let Err(err) = receive(&client, &engine, &ast, &subscription, &mut delay) {
+            log::warn!(channel, err = err.to_string(), retry_ms = delay.as_millis() as u64, "Subscription lost, reconnecting");
+        }
+
+        thread::sleep(delay);
+        delay = (delay * 2).min(MAX_DELAY);
+    }
+}
+
+/// Holds a dedicated connection and runs the handler for each message until it fails.
+fn receive(client: &RedisClient, engine: &Engine, ast: &AST, subscription: &Subscription, delay: &mut Duration) -> RedisResult<()> {
+    let mut conn = client.get_connection()?;
+    let mut pubsub = conn.as_pubsub();
+
+    pubsub.subscribe(&subscription.channel)?;
+    *delay = Duration::from_millis(500);
+    log::info!(channel = subscription.channel, handler = subscription.route.fn_name.as_str(), "subscribed");
+
+    loop {
+        let message = pubsub.get_message()?;
+        let payload: String = message.get_payload().unwrap_or_default();
+
+        // handlers may take the payload and the channel, in that order
+        let arity = subscription.route.args.as_ref().map_or(0, |args| args.len());
+        let args: Vec<Dynamic> = [Dynamic::from(payload), Dynamic::from(message.get_channel_name().to_string())].into_iter().take(arity).collect();
+
+        if let Err(err) = engine.call_fn::<Dynamic>(&mut http::globals(), ast, subscription.route.fn_name.as_str(), args) {
+            log::error!(channel = subscription.channel, err = err.to_string(), "Subscription handler failed");
+        }
+    }
+}
@@ -1,175 +1,477 @@
-use crate::{prelude::*, structs::config::Config, structs::modules::*};
-use macros_rs::fmt::string;
-use redis::{Client as RedisClient, Commands};
-use rhai::{plugin::*, serde::to_dynamic, FnNamespace};
+use crate::{
+    database::{mongo::plain, pool},
+    structs::modules::*,
+};
+use r2d2::PooledConnection;
+use redis::{Client as RedisClient, Cmd, FromRedisValue, Value};
+use rhai::{plugin::*, Array, FnNamespace, Map, INT};
 use std::collections::BTreeMap;
 
+fn connection(redis: &Redis) -> Result<PooledConnection<RedisClient>, Box<EvalAltResult>> { redis.pool.get().map_err(|err| format!("Cannot connect to redis, {err}").into()) }
+
+fn query<T: FromRedisValue>(redis: &Redis, cmd: &Cmd) -> Result<T, Box<EvalAltResult>> {
+    let mut conn = connection(redis)?;
+    cmd.query(&mut *conn).map_err(|err| err.to_string().into())
+}
+
+/// Redis replies as script values, bulk strings become strings and nil becomes `()`.
+fn to_dynamic(value: Value) -> Dynamic {
+    match value {
+        Value::Nil => Dynamic::UNIT,
+        Value::Int(int) => Dynamic::from_int(int),
+        Value::Data(data) => String::from_utf8_lossy(&data).to_string().into(),
+        Value::Bulk(items) => items.into_iter().map(to_dynamic).collect::<Array>().into(),
+        Value::Status(status) => status.into(),
+        Value::Okay => "OK".into(),
+    }
+}
+
+/// Script values as command arguments, maps and arrays are stored as json.
+fn to_arg(value: &Dynamic) -> String {
+    let value = plain(value.clone());
+
+    match value.clone().into_string() {
+        Ok(string) => string,
+        Err(_) if value.is_map() || value.is_array() => serde_json::to_string(&value).unwrap_or_default(),
+        Err(_) => value.to_string(),
+    }
+}
+
+/// Adds `value` to `cmd`, spreading arrays into one argument per item.
+fn spread(cmd: &mut Cmd, value: &Dynamic) {
+    match value.read_lock::<Array>() {
+        Some(items) => {
+            for item in items.iter() {
+                cmd.arg(to_arg(item));
+            }
+        }
+        None => {
+            cmd.arg(to_arg(value));
+        }
+    }
+}
+
+fn strings(items: Vec<String>) -> Array { items.into_iter().map(Dynamic::from).collect() }
+
+fn command(name: &str, args: &Array) -> Cmd {
+    let mut cmd = redis::cmd(&name.to_uppercase());
+    for arg in args {
+        cmd.arg(to_arg(arg));
+    }
+    cmd
+}
+
+/// Walks the keyspace with SCAN so large databases are never blocked like with KEYS.
+fn scan(redis: &Redis, pattern: &str) -> Result<Vec<String>, Box<EvalAltResult>> {
+    let mut conn = connection(redis)?;
+    let keys = redis::cmd("SCAN").cursor_arg(0).arg("MATCH").arg(pattern).arg("COUNT").arg(100).clone().iter::<String>(&mut *conn);
+
+    match keys {
+        Ok(keys) => Ok(keys.collect()),
+        Err(err) => Err(err.to_string().into()),
+    }
+}
+
+fn values(redis: &Redis, pattern: &str) -> Result<Dynamic, Box<EvalAltResult>> {
+    let keys = scan(redis, pattern)?;
+    let values: Vec<Option<String>> = match keys.is_empty() {
+        true => vec![],
+        false => query(redis, redis::cmd("MGET").arg(&keys))?,
+    };
+
+    let items = keys.into_iter().zip(values).map(|(key, value)| (key.into(), value.unwrap_or_default().into())).collect::<BTreeMap<_, Dynamic>>();
+    Ok(Dynamic::from_map(items))
+}
+
+/// Stream entries as `#{id, fields}` maps.
+fn stream_entries(value: Value) -> Array {
+    let Value::Bulk(entries) = value else { return Array::new() };
+
+    entries
+        .into_iter()
+        .filter_map(|entry| match entry {
+            Value::Bulk(mut parts) if parts.len() == 2 => {
+                let fields = parts.pop().unwrap_or(Value::Nil);
+                let id = to_dynamic(parts.pop().unwrap_or(Value::Nil));
+
+                let fields: Map = match fields {
+                    Value::Bulk(items) => items.chunks(2).filter_map(|pair| Some((to_dynamic(pair.first()?.clone()).to_string().into(), to_dynamic(pair.get(1)?.clone())))).collect(),
+                    _ => Map::new(),
+                };
+
+                let mut entry = Map::new();
+                entry.insert("id".into(), id);
+                entry.insert("fields".into(), fields.into());
+                Some(Dynamic::from_map(entry))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The entries of the only stream in an XREAD or XREADGROUP reply, nil means nothing new.
+fn read_entries(value: Value) -> Array {
+    match value {
+        Value::Bulk(streams) => streams
+            .into_iter()
+            .flat_map(|stream| match stream {
+                Value::Bulk(mut parts) if parts.len() == 2 => stream_entries(parts.pop().unwrap_or(Value::Nil)),
+                _ => Array::new(),
+            })
+            .collect(),
+        _ => Array::new(),
+    }
+}
+
+fn xadd(redis: &Redis, key: String, fields: Map, maxlen: Option<INT>) -> Result<String, Box<EvalAltResult>> {
+    let mut cmd = redis::cmd("XADD");
+    cmd.arg(key);
+
+    if let Some(maxlen) = maxlen {
+        cmd.arg("MAXLEN").arg("~").arg(maxlen);
+    }
+
+    cmd.arg("*");
+    for (field, value) in &fields {
+        cmd.arg(field.as_str()).arg(to_arg(value));
+    }
+
+    query(redis, &cmd)
+}
+
 #[export_module]
 pub mod redis_db {
-    pub fn connect() -> Redis {
-        // add error handling
-        // .get_db(Database::Redis)
-        let config = Config::new().set_path(&crate::Cli::parse().config).read().database.unwrap();
-        match RedisClient::open(config.redis.unwrap().server) {
-            Ok(client) => Redis { client: Some(client) },
-            Err(_) => Redis { client: None },
+    use super::{query, read_entries, spread, stream_entries, strings, to_arg, to_dynamic};
+
+    #[rhai_fn(return_raw)]
+    pub fn connect() -> Result<Redis, Box<EvalAltResult>> {
+        match pool::redis(None) {
+            Ok(pool) => Ok(Redis { pool }),
+            Err(err) => Err(err.into()),
         }
     }
 
-    #[rhai_fn(global, return_raw, name = "set")]
-    pub fn set_string(redis: Redis, key: String, value: String) -> Result<(), Box<EvalAltResult>> {
-        let mut conn = redis.client.unwrap().get_connection().unwrap();
-        match conn.set::<String, String, ()>(key, value) {
-            Ok(_) => Ok(()),
-            Err(err) => Err(err.to_string().into()),
+    #[rhai_fn(return_raw, name = "connect")]
+    pub fn connect_named(name: &str) -> Result<Redis, Box<EvalAltResult>> {
+        match pool::redis(Some(name)) {
+            Ok(pool) => Ok(Redis { pool }),
+            Err(err) => Err(err.into()),
         }
     }
 
     #[rhai_fn(global, return_raw, name = "set")]
-    pub fn set_i64(redis: Redis, key: String, value: i64) -> Result<(), Box<EvalAltResult>> {
-        let mut conn = redis.client.unwrap().get_connection().unwrap();
-        match conn.set::<String, i64, ()>(key, value) {
-            Ok(_) => Ok(()),
-            Err(err) => Err(err.to_string().into()),
+    pub fn set_string(redis: Redis, key: String, value: String) -> Result<(), Box<EvalAltResult>> { query(&redis, redis::cmd("SET").arg(key).arg(value)) }
+
+    #[rhai_fn(global, return_raw, name = "set")]
+    pub fn set_i64(redis: Redis, key: String, value: i64) -> Result<(), Box<EvalAltResult>> { query(&redis, redis::cmd("SET").arg(key).arg(value)) }
+
+    /// The value of `key`, or `()` when it is missing so it is never mistaken for an empty string.
+    #[rhai_fn(global, return_raw, name = "get")]
+    pub fn get(redis: Redis, key: String) -> Result<Dynamic, Box<EvalAltResult>> { Ok(to_dynamic(query(&redis, redis::cmd("GET").arg(key))?)) }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn setex(redis: Redis, key: String, value: Dynamic, seconds: INT) -> Result<(), Box<EvalAltResult>> { query(&redis, redis::cmd("SETEX").arg(key).arg(seconds).arg(to_arg(&value))) }
+
+    /// Sets `key` only when it is missing, returns whether it was set.
+    #[rhai_fn(global, return_raw)]
+    pub fn setnx(redis: Redis, key: String, value: Dynamic) -> Result<bool, Box<EvalAltResult>> { query(&redis, redis::cmd("SETNX").arg(key).arg(to_arg(&value))) }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn mget(redis: Redis, keys: Array) -> Result<Array, Box<EvalAltResult>> {
+        let mut cmd = redis::cmd("MGET");
+        for key in &keys {
+            cmd.arg(to_arg(key));
         }
+        Ok(query::<Vec<Value>>(&redis, &cmd)?.into_iter().map(to_dynamic).collect())
     }
 
-    #[rhai_fn(global, name = "get")]
-    pub fn get(redis: Redis, key: String) -> String {
-        let mut conn = redis.client.unwrap().get_connection().unwrap();
-        match conn.get::<String, String>(key) {
-            Ok(data) => data,
-            Err(_) => string!(""),
+    #[rhai_fn(global, return_raw)]
+    pub fn mset(redis: Redis, values: Map) -> Result<(), Box<EvalAltResult>> {
+        let mut cmd = redis::cmd("MSET");
+        for (key, value) in &values {
+            cmd.arg(key.as_str()).arg(to_arg(value));
         }
+        query(&redis, &cmd)
     }
 
-    #[rhai_fn(global)]
-    pub fn del(redis: Redis, key: String) -> String {
-        let mut conn = redis.client.unwrap().get_connection().unwrap();
-        match conn.del(key) {
-            Ok(data) => data,
-            Err(err) => err.to_string(),
-        }
+    /// Deletes `key`, returns how many keys were removed.
+    #[rhai_fn(global, return_raw)]
+    pub fn del(redis: Redis, key: Dynamic) -> Result<INT, Box<EvalAltResult>> {
+        let mut cmd = redis::cmd("DEL");
+        spread(&mut cmd, &key);
+        query(&redis, &cmd)
     }
 
-    #[rhai_fn(global)]
-    pub fn expire(redis: Redis, key: String, s: i64) -> String {
-        let mut conn = redis.client.unwrap().get_connection().unwrap();
-        match conn.expire(key, s) {
-            Ok(data) => data,
-            Err(err) => err.to_string(),
+    #[rhai_fn(global, return_raw)]
+    pub fn expire(redis: Redis, key: String, s: i64) -> Result<bool, Box<EvalAltResult>> { query(&redis, redis::cmd("EXPIRE").arg(key).arg(s)) }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn persist(redis: Redis, key: String) -> Result<bool, Box<EvalAltResult>> { query(&redis, redis::cmd("PERSIST").arg(key)) }
+
+    /// Seconds until `key` expires, -1 without an expiry and -2 when missing.
+    #[rhai_fn(global, return_raw)]
+    pub fn ttl(redis: Redis, key: String) -> Result<INT, Box<EvalAltResult>> { query(&redis, redis::cmd("TTL").arg(key)) }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn rename(redis: Redis, key: String, new: String) -> Result<(), Box<EvalAltResult>> { query(&redis, redis::cmd("RENAME").arg(key).arg(new)) }
+
+    /// Appends `value` to `key`, returns the new length.
+    #[rhai_fn(global, return_raw)]
+    pub fn append(redis: Redis, key: String, value: String) -> Result<INT, Box<EvalAltResult>> { query(&redis, redis::cmd("APPEND").arg(key).arg(value)) }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn inc(redis: Redis, key: String, value: i64) -> Result<INT, Box<EvalAltResult>> { query(&redis, redis::cmd("INCRBY").arg(key).arg(value)) }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn dec(redis: Redis, key: String, value: i64) -> Result<INT, Box<EvalAltResult>> { query(&redis, redis::cmd("DECRBY").arg(key).arg(value)) }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn exists(redis: Redis, key: String) -> Result<bool, Box<EvalAltResult>> { query(&redis, redis::cmd("EXISTS").arg(key)) }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn keys(redis: Redis, filter: String) -> Result<Array, Box<EvalAltResult>> { Ok(strings(super::scan(&redis, &filter)?)) }
+
+    /// Keys matching the glob `pattern`, found with SCAN.
+    #[rhai_fn(global, return_raw)]
+    pub fn scan(redis: Redis, pattern: String) -> Result<Array, Box<EvalAltResult>> { Ok(strings(super::scan(&redis, &pattern)?)) }
+
+    #[rhai_fn(global, return_raw, name = "list")]
+    pub fn list_all(redis: Redis) -> Result<Dynamic, Box<EvalAltResult>> { super::values(&redis, "*") }
+
+    #[rhai_fn(global, return_raw, name = "list")]
+    pub fn list_filter(redis: Redis, filter: String) -> Result<Dynamic, Box<EvalAltResult>> { super::values(&redis, &filter) }
+
+    #[rhai_fn(global, return_raw, name = "hset")]
+    pub fn hset(redis: Redis, key: String, field: String, value: Dynamic) -> Result<INT, Box<EvalAltResult>> { query(&redis, redis::cmd("HSET").arg(key).arg(field).arg(to_arg(&value))) }
+
+    /// Sets every field of `values`, returns how many fields are new.
+    #[rhai_fn(global, return_raw, name = "hset")]
+    pub fn hset_map(redis: Redis, key: String, values: Map) -> Result<INT, Box<EvalAltResult>> {
+        let mut cmd = redis::cmd("HSET");
+        cmd.arg(key);
+        for (field, value) in &values {
+            cmd.arg(field.as_str()).arg(to_arg(value));
         }
+        query(&redis, &cmd)
     }
 
-    #[rhai_fn(global)]
-    pub fn persist(redis: Redis, key: String) -> String {
-        let mut conn = redis.client.unwrap().get_connection().unwrap();
-        match conn.persist(key) {
-            Ok(data) => data,
-            Err(err) => err.to_string(),
-        }
+    #[rhai_fn(global, return_raw)]
+    pub fn hget(redis: Redis, key: String, field: String) -> Result<Dynamic, Box<EvalAltResult>> { Ok(to_dynamic(query(&redis, redis::cmd("HGET").arg(key).arg(field))?)) }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn hgetall(redis: Redis, key: String) -> Result<Map, Box<EvalAltResult>> {
+        let fields: BTreeMap<String, Value> = query(&redis, redis::cmd("HGETALL").arg(key))?;
+        Ok(fields.into_iter().map(|(field, value)| (field.into(), to_dynamic(value))).collect())
     }
 
-    #[rhai_fn(global)]
-    pub fn ttl(redis: Redis, key: String) -> String {
-        let mut conn = redis.client.unwrap().get_connection().unwrap();
-        match conn.ttl(key) {
-            Ok(data) => data,
-            Err(err) => err.to_string(),
-        }
+    #[rhai_fn(global, return_raw)]
+    pub fn hdel(redis: Redis, key: String, field: Dynamic) -> Result<INT, Box<EvalAltResult>> {
+        let mut cmd = redis::cmd("HDEL");
+        cmd.arg(key);
+        spread(&mut cmd, &field);
+        query(&redis, &cmd)
     }
 
-    #[rhai_fn(global)]
-    pub fn rename(redis: Redis, key: String, new: String) -> String {
-        let mut conn = redis.client.unwrap().get_connection().unwrap();
-        match conn.rename(key, new) {
-            Ok(data) => data,
-            Err(err) => err.to_string(),
-        }
+    #[rhai_fn(global, return_raw)]
+    pub fn hexists(redis: Redis, key: String, field: String) -> Result<bool, Box<EvalAltResult>> { query(&redis, redis::cmd("HEXISTS").arg(key).arg(field)) }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn hincr(redis: Redis, key: String, field: String, by: INT) -> Result<INT, Box<EvalAltResult>> { query(&redis, redis::cmd("HINCRBY").arg(key).arg(field).arg(by)) }
+
+    /// Pushes a value, or every item of an array, returns the new length.
+    #[rhai_fn(global, return_raw)]
+    pub fn lpush(redis: Redis, key: String, value: Dynamic) -> Result<INT, Box<EvalAltResult>> {
+        let mut cmd = redis::cmd("LPUSH");
+        cmd.arg(key);
+        spread(&mut cmd, &value);
+        query(&redis, &cmd)
     }
 
-    #[rhai_fn(global)]
-    pub fn append(redis: Redis, key: String, value: String) -> String {
-        let mut conn = redis.client.unwrap().get_connection().unwrap();
-        match conn.append(key, value) {
-            Ok(data) => data,
-            Err(err) => err.to_string(),
-        }
+    #[rhai_fn(global, return_raw)]
+    pub fn rpush(redis: Redis, key: String, value: Dynamic) -> Result<INT, Box<EvalAltResult>> {
+        let mut cmd = redis::cmd("RPUSH");
+        cmd.arg(key);
+        spread(&mut cmd, &value);
+        query(&redis, &cmd)
     }
 
-    #[rhai_fn(global)]
-    pub fn inc(redis: Redis, key: String, value: i64) -> String {
-        let mut conn = redis.client.unwrap().get_connection().unwrap();
-        match conn.incr(key, value) {
-            Ok(data) => data,
-            Err(err) => err.to_string(),
-        }
+    #[rhai_fn(global, return_raw)]
+    pub fn lpop(redis: Redis, key: String) -> Result<Dynamic, Box<EvalAltResult>> { Ok(to_dynamic(query(&redis, redis::cmd("LPOP").arg(key))?)) }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn rpop(redis: Redis, key: String) -> Result<Dynamic, Box<EvalAltResult>> { Ok(to_dynamic(query(&redis, redis::cmd("RPOP").arg(key))?)) }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn lrange(redis: Redis, key: String, start: INT, stop: INT) -> Result<Array, Box<EvalAltResult>> { Ok(strings(query(&redis, redis::cmd("LRANGE").arg(key).arg(start).arg(stop))?)) }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn llen(redis: Redis, key: String) -> Result<INT, Box<EvalAltResult>> { query(&redis, redis::cmd("LLEN").arg(key)) }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn sadd(redis: Redis, key: String, member: Dynamic) -> Result<INT, Box<EvalAltResult>> {
+        let mut cmd = redis::cmd("SADD");
+        cmd.arg(key);
+        spread(&mut cmd, &member);
+        query(&redis, &cmd)
     }
 
-    #[rhai_fn(global)]
-    pub fn dec(redis: Redis, key: String, value: i64) -> String {
-        let mut conn = redis.client.unwrap().get_connection().unwrap();
-        match conn.decr(key, value) {
-            Ok(data) => data,
-            Err(err) => err.to_string(),
-        }
+    #[rhai_fn(global, return_raw)]
+    pub fn srem(redis: Redis, key: String, member: Dynamic) -> Result<INT, Box<EvalAltResult>> {
+        let mut cmd = redis::cmd("SREM");
+        cmd.arg(key);
+        spread(&mut cmd, &member);
+        query(&redis, &cmd)
     }
 
-    #[rhai_fn(global)]
-    pub fn exists(redis: Redis, key: String) -> bool {
-        let mut conn = redis.client.unwrap().get_connection().unwrap();
-        match conn.exists(key) {
-            Ok(bool) => bool,
-            Err(_) => false,
-        }
+    #[rhai_fn(global, return_raw)]
+    pub fn smembers(redis: Redis, key: String) -> Result<Array, Box<EvalAltResult>> {
+        let mut members: Vec<String> = query(&redis, redis::cmd("SMEMBERS").arg(key))?;
+        members.sort();
+        Ok(strings(members))
     }
 
     #[rhai_fn(global, return_raw)]
-    pub fn keys(redis: Redis, filter: String) -> Result<Dynamic, Box<EvalAltResult>> {
-        let mut conn = redis.client.unwrap().get_connection().unwrap();
-        match conn.keys(filter) {
-            Ok(data) => to_dynamic::<Vec<String>>(data),
-            Err(_) => to_dynamic::<Vec<String>>(vec![]),
-        }
+    pub fn sismember(redis: Redis, key: String, member: Dynamic) -> Result<bool, Box<EvalAltResult>> { query(&redis, redis::cmd("SISMEMBER").arg(key).arg(to_arg(&member))) }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn scard(redis: Redis, key: String) -> Result<INT, Box<EvalAltResult>> { query(&redis, redis::cmd("SCARD").arg(key)) }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn zadd(redis: Redis, key: String, member: Dynamic, score: Dynamic) -> Result<INT, Box<EvalAltResult>> { query(&redis, redis::cmd("ZADD").arg(key).arg(to_arg(&score)).arg(to_arg(&member))) }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn zrem(redis: Redis, key: String, member: Dynamic) -> Result<INT, Box<EvalAltResult>> {
+        let mut cmd = redis::cmd("ZREM");
+        cmd.arg(key);
+        spread(&mut cmd, &member);
+        query(&redis, &cmd)
     }
 
-    #[rhai_fn(global, return_raw, name = "list")]
-    pub fn list_all(redis: Redis) -> Result<Dynamic, Box<EvalAltResult>> {
-        let mut conn = redis.client.unwrap().get_connection().unwrap();
+    /// The score of `member`, or `()` when it is not in the set.
+    #[rhai_fn(global, return_raw)]
+    pub fn zscore(redis: Redis, key: String, member: Dynamic) -> Result<Dynamic, Box<EvalAltResult>> {
+        let score: Option<f64> = query(&redis, redis::cmd("ZSCORE").arg(key).arg(to_arg(&member)))?;
+        Ok(score.map(Dynamic::from_float).unwrap_or(Dynamic::UNIT))
+    }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn zincr(redis: Redis, key: String, member: Dynamic, by: Dynamic) -> Result<f64, Box<EvalAltResult>> { query(&redis, redis::cmd("ZINCRBY").arg(key).arg(to_arg(&by)).arg(to_arg(&member))) }
+
+    /// Members by rank, lowest score first.
+    #[rhai_fn(global, return_raw)]
+    pub fn zrange(redis: Redis, key: String, start: INT, stop: INT) -> Result<Array, Box<EvalAltResult>> { Ok(strings(query(&redis, redis::cmd("ZRANGE").arg(key).arg(start).arg(stop))?)) }
 
-        let keys = match conn.keys("*") {
-            Ok(data) => data,
-            Err(_) => vec![],
-        };
+    /// Like `zrange`, as `#{member, score}` maps.
+    #[rhai_fn(global, return_raw)]
+    pub fn zrange_scores(redis: Redis, key: String, start: INT, stop: INT) -> Result<Array, Box<EvalAltResult>> {
+        let members: Vec<(String, f64)> = query(&redis, redis::cmd("ZRANGE").arg(key).arg(start).arg(stop).arg("WITHSCORES"))?;
 
-        let items = keys
+        Ok(members
             .into_iter()
-            .map(|key| {
-                let value: Option<String> = conn.get(&key).unwrap();
-                (key, value.unwrap_or_else(|| "".to_string()))
+            .map(|(member, score)| {
+                let mut entry = Map::new();
+                entry.insert("member".into(), member.into());
+                entry.insert("score".into(), score.into());
+                Dynamic::from_map(entry)
             })
-            .collect::<BTreeMap<String, String>>();
+            .collect())
+    }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn zcard(redis: Redis, key: String) -> Result<INT, Box<EvalAltResult>> { query(&redis, redis::cmd("ZCARD").arg(key)) }
+
+    /// Runs any redis command, for anything without its own method.
+    #[rhai_fn(global, return_raw)]
+    pub fn command(redis: Redis, name: String, args: Array) -> Result<Dynamic, Box<EvalAltResult>> { Ok(to_dynamic(query(&redis, &super::command(&name, &args))?)) }
+
+    /// Queues commands to send in a single round trip.
+    #[rhai_fn(global)]
+    pub fn pipeline(redis: Redis) -> RedisPipeline { RedisPipeline { pool: redis.pool, pipe: redis::pipe() } }
 
-        to_dynamic(items)
+    /// Like `pipeline`, but wrapped in MULTI/EXEC so the commands apply atomically.
+    #[rhai_fn(global)]
+    pub fn multi(redis: Redis) -> RedisPipeline {
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        RedisPipeline { pool: redis.pool, pipe }
     }
 
-    #[rhai_fn(global, return_raw, name = "list")]
-    pub fn list_filter(redis: Redis, filter: String) -> Result<Dynamic, Box<EvalAltResult>> {
-        let mut conn = redis.client.unwrap().get_connection().unwrap();
+    #[rhai_fn(global, name = "queue")]
+    pub fn queue(pipeline: &mut RedisPipeline, name: String) { pipeline.pipe.add_command(super::command(&name, &Array::new())); }
 
-        let keys = match conn.keys(filter) {
-            Ok(data) => data,
-            Err(_) => vec![],
-        };
+    #[rhai_fn(global, name = "queue")]
+    pub fn queue_args(pipeline: &mut RedisPipeline, name: String, args: Array) { pipeline.pipe.add_command(super::command(&name, &args)); }
 
-        let items = keys
-            .into_iter()
-            .map(|key| {
-                let value: Option<String> = conn.get(&key).unwrap();
-                (key, value.unwrap_or_else(|| "".to_string()))
-            })
-            .collect::<BTreeMap<String, String>>();
+    /// Sends every queued command, returns their replies in order.
+    #[rhai_fn(global, return_raw)]
+    pub fn exec(pipeline: &mut RedisPipeline) -> Result<Array, Box<EvalAltResult>> {
+        let redis = Redis { pool: pipeline.pool.clone() };
+        let mut conn = super::connection(&redis)?;
+
+        match pipeline.pipe.query::<Vec<Value>>(&mut *conn) {
+            Ok(replies) => Ok(replies.into_iter().map(to_dynamic).collect()),
+            Err(err) => Err(err.to_string().into()),
+        }
+    }
+
+    /// Publishes on the default connection, returns how many subscribers received it.
+    #[rhai_fn(return_raw, name = "publish")]
+    pub fn publish_default(channel: String, message: Dynamic) -> Result<INT, Box<EvalAltResult>> { publish(connect()?, channel, message) }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn publish(redis: Redis, channel: String, message: Dynamic) -> Result<INT, Box<EvalAltResult>> { query(&redis, redis::cmd("PUBLISH").arg(channel).arg(to_arg(&message))) }
+
+    /// Appends an entry to a stream, returns its id.
+    #[rhai_fn(global, return_raw)]
+    pub fn xadd(redis: Redis, key: String, fields: Map) -> Result<String, Box<EvalAltResult>> { super::xadd(&redis, key, fields, None) }
+
+    /// Like `xadd`, trimming the stream to roughly `maxlen` entries.
+    #[rhai_fn(global, return_raw, name = "xadd")]
+    pub fn xadd_maxlen(redis: Redis, key: String, fields: Map, maxlen: INT) -> Result<String, Box<EvalAltResult>> { super::xadd(&redis, key, fields, Some(maxlen)) }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn xlen(redis: Redis, key: String) -> Result<INT, Box<EvalAltResult>> { query(&redis, redis::cmd("XLEN").arg(key)) }
+
+    /// Entries between two ids, use "-" and "+" for the whole stream.
+    #[rhai_fn(global, return_raw)]
+    pub fn xrange(redis: Redis, key: String, start: String, end: String) -> Result<Array, Box<EvalAltResult>> { Ok(stream_entries(query(&redis, redis::cmd("XRANGE").arg(key).arg(start).arg(end))?)) }
+
+    /// Entries after `id`, without blocking.
+    #[rhai_fn(global, return_raw)]
+    pub fn xread(redis: Redis, key: String, id: String) -> Result<Array, Box<EvalAltResult>> { Ok(read_entries(query(&redis, redis::cmd("XREAD").arg("STREAMS").arg(key).arg(id))?)) }
+
+    #[rhai_fn(global, return_raw, name = "xread")]
+    pub fn xread_count(redis: Redis, key: String, id: String, count: INT) -> Result<Array, Box<EvalAltResult>> {
+        Ok(read_entries(query(&redis, redis::cmd("XREAD").arg("COUNT").arg(count).arg("STREAMS").arg(key).arg(id))?))
+    }
+
+    /// Creates a consumer group reading from `id`, creating the stream when missing,
+    /// returns false when the group already exists.
+    #[rhai_fn(global, return_raw)]
+    pub fn xgroup_create(redis: Redis, key: String, group: String, id: String) -> Result<bool, Box<EvalAltResult>> {
+        let mut conn = super::connection(&redis)?;
 
-        to_dynamic(items)
+        match redis::cmd("XGROUP").arg("CREATE").arg(key).arg(group).arg(id).arg("MKSTREAM").query::<()>(&mut *conn) {
+            Ok(_) => Ok(true),
+            Err(err) if err.code() == Some("BUSYGROUP") => Ok(false),
+            Err(err) => Err(err.to_string().into()),
+        }
+    }
+
+    /// Up to `count` entries never delivered to the group, each must be acknowledged with `xack`.
+    #[rhai_fn(global, return_raw)]
+    pub fn xreadgroup(redis: Redis, key: String, group: String, consumer: String, count: INT) -> Result<Array, Box<EvalAltResult>> {
+        Ok(read_entries(query(
+            &redis,
+            redis::cmd("XREADGROUP").arg("GROUP").arg(group).arg(consumer).arg("COUNT").arg(count).arg("STREAMS").arg(key).arg(">"),
+        )?))
+    }
+
+    #[rhai_fn(global, return_raw)]
+    pub fn xack(redis: Redis, key: String, group: String, id: Dynamic) -> Result<INT, Box<EvalAltResult>> {
+        let mut cmd = redis::cmd("XACK");
+        cmd.arg(key).arg(group);
+        spread(&mut cmd, &id);
+        query(&redis, &cmd)
     }
 }
@@ -0,0 +1,205 @@
+use crate::{database::pool, structs::modules::*};
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use rhai::{plugin::*, Array, Blob, FnNamespace, FnPtr, Map, FLOAT, INT};
+use std::sync::{Arc, Mutex};
+
+use rusqlite::{
+    params_from_iter,
+    types::{Value, ValueRef},
+    Connection, Statement,
+};
+
+fn error(err: rusqlite::Error) -> Box<EvalAltResult> { err.to_string().into() }
+
+fn connection(sql: &Sql) -> Result<PooledConnection<SqliteConnectionManager>, Box<EvalAltResult>> { sql.pool.get().map_err(|err| format!("Cannot connect to sqlite, {err}").into()) }
+
+fn default() -> Result<Sql, Box<EvalAltResult>> { pool::sqlite(None).map(|pool| Sql { pool }).map_err(Into::into) }
+
+/// Script values as sqlite values, maps and arrays are stored as json text.
+fn to_value(value: &Dynamic) -> Result<Value, String> {
+    if value.is_unit() {
+        return Ok(Value::Null);
+    }
+    if let Ok(int) = value.as_int() {
+        return Ok(Value::Integer(int));
+    }
+    if let Ok(float) = value.as_float() {
+        return Ok(Value::Real(float));
+    }
+    if let Ok(bool) = value.as_bool() {
+        return Ok(Value::Integer(bool as i64));
+    }
+    if let Some(bytes) = value.read_lock::<Blob>() {
+        return Ok(Value::Blob(bytes.to_vec()));
+    }
+    if value.is_map() || value.is_array() {
+        return serde_json::to_string(value).map(Value::Text).map_err(|err| err.to_string());
+    }
+
+    match value.clone().into_string() {
+        Ok(string) => Ok(Value::Text(string)),
+        Err(kind) => Err(format!("{} cannot be stored in sqlite", kind.rsplit("::").next().unwrap_or_default())),
+    }
+}
+
+/// Columns as script values, integers stay ints and blobs become blobs.
+fn from_value(value: ValueRef) -> Dynamic {
+    match value {
+        ValueRef::Null => Dynamic::UNIT,
+        ValueRef::Integer(int) => (int as INT).into(),
+        ValueRef::Real(float) => (float as FLOAT).into(),
+        ValueRef::Text(text) => String::from_utf8_lossy(text).to_string().into(),
+        ValueRef::Blob(bytes) => Dynamic::from_blob(bytes.to_vec()),
+    }
+}
+
+/// Binds an array to `?` placeholders in order, or a map to `:name` placeholders.
+/// A single value is bound to the only placeholder.
+fn bind(stmt: &Statement, params: &Dynamic) -> Result<Vec<Value>, Box<EvalAltResult>> {
+    if params.is_unit() {
+        return Ok(vec![]);
+    }
+
+    if let Some(items) = params.read_lock::<Array>() {
+        return items.iter().enumerate().map(|(index, item)| to_value(item).map_err(|err| format!("parameter {}: {err}", index + 1).into())).collect();
+    }
+
+    let Some(map) = params.read_lock::<Map>() else {
+        return Ok(vec![to_value(params)?]);
+    };
+
+    let mut values: Vec<Option<Value>> = vec![None; stmt.parameter_count()];
+
+    for (key, value) in map.iter() {
+        let name = match key.starts_with([':', '@', '$']) {
+            true => key.to_string(),
+            false => format!(":{key}"),
+        };
+
+        match stmt.parameter_index(&name).map_err(error)? {
+            Some(index) => values[index - 1] = Some(to_value(value).map_err(|err| format!("parameter {name}: {err}"))?),
+            None => return Err(format!("No parameter named {name} in query").into()),
+        }
+    }
+
+    // a placeholder the map leaves out is an error rather than a silent null
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(index, value)| value.ok_or_else(|| format!("missing parameter {}", stmt.parameter_name(index + 1).map(String::from).unwrap_or_else(|| format!("?{}", index + 1))).into()))
+        .collect()
+}
+
+fn query(conn: &Connection, sql: &str, params: &Dynamic) -> Result<Array, Box<EvalAltResult>> {
+    let mut stmt = conn.prepare_cached(sql).map_err(error)?;
+    let values = bind(&stmt, params)?;
+    let names: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+
+    let mut rows = stmt.query(params_from_iter(values)).map_err(error)?;
+    let mut items = Array::new();
+
+    while let Some(row) = rows.next().map_err(error)? {
+        let mut item = Map::new();
+        for (index, name) in names.iter().enumerate() {
+            item.insert(name.into(), from_value(row.get_ref(index).map_err(error)?));
+        }
+        items.push(Dynamic::from_map(item));
+    }
+
+    Ok(items)
+}
+
+fn execute(conn: &Connection, sql: &str, params: &Dynamic) -> Result<INT, Box<EvalAltResult>> {
+    let mut stmt = conn.prepare_cached(sql).map_err(error)?;
+    let values = bind(&stmt, params)?;
+
+    stmt.execute(params_from_iter(values)).map(|changes| changes as INT).map_err(error)
+}
+
+/// Runs `callback` with a transaction, committing when it returns and rolling back when it throws.
+fn transaction(context: &NativeCallContext, sql: &Sql, callback: FnPtr) -> Result<Dynamic, Box<EvalAltResult>> {
+    let conn = connection(sql)?;
+
+    // take the write lock up front, so two transactions never fail upgrading a read lock
+    conn.execute_batch("BEGIN IMMEDIATE").map_err(error)?;
+
+    let tx = SqlTransaction { conn: Arc::new(Mutex::new(Some(conn))) };
+    let result = callback.call_within_context::<Dynamic>(context, (tx.clone(),));
+
+    let Some(conn) = tx.conn.lock().ok().and_then(|mut conn| conn.take()) else {
+        return Err("Transaction connection was lost".into());
+    };
+
+    match result {
+        Ok(value) => conn.execute_batch("COMMIT").map(|_| value).map_err(error),
+        Err(err) => {
+            if let Err(rollback) = conn.execute_batch("ROLLBACK") {
+                log::warn!(err = rollback.to_string(), "Cannot roll back sqlite transaction");
+            }
+            Err(err)
+        }
+    }
+}
+
+fn with_transaction<T>(tx: &SqlTransaction, run: impl FnOnce(&Connection) -> Result<T, Box<EvalAltResult>>) -> Result<T, Box<EvalAltResult>> {
+    let conn = tx.conn.lock().map_err(|err| err.to_string())?;
+
+    match conn.as_ref() {
+        Some(conn) => run(conn),
+        None => Err("Transaction has already finished".into()),
+    }
+}
+
+#[export_module]
+pub mod sql_db {
+    #[rhai_fn(return_raw)]
+    pub fn connect() -> Result<Sql, Box<EvalAltResult>> { default() }
+
+    #[rhai_fn(return_raw, name = "connect")]
+    pub fn connect_named(name: &str) -> Result<Sql, Box<EvalAltResult>> { pool::sqlite(Some(name)).map(|pool| Sql { pool }).map_err(Into::into) }
+
+    /// Rows as an array of maps keyed by column name.
+    #[rhai_fn(return_raw, name = "query")]
+    pub fn query_default(sql: &str) -> Result<Array, Box<EvalAltResult>> { query_params(&mut default()?, sql, Dynamic::UNIT) }
+
+    #[rhai_fn(return_raw, name = "query")]
+    pub fn query_default_params(sql: &str, params: Dynamic) -> Result<Array, Box<EvalAltResult>> { query_params(&mut default()?, sql, params) }
+
+    /// Number of rows changed.
+    #[rhai_fn(return_raw, name = "execute")]
+    pub fn execute_default(sql: &str) -> Result<INT, Box<EvalAltResult>> { execute_params(&mut default()?, sql, Dynamic::UNIT) }
+
+    #[rhai_fn(return_raw, name = "execute")]
+    pub fn execute_default_params(sql: &str, params: Dynamic) -> Result<INT, Box<EvalAltResult>> { execute_params(&mut default()?, sql, params) }
+
+    #[rhai_fn(return_raw, name = "transaction")]
+    pub fn transaction_default(context: NativeCallContext, callback: FnPtr) -> Result<Dynamic, Box<EvalAltResult>> { transaction(&context, &default()?, callback) }
+
+    #[rhai_fn(global, return_raw, name = "query")]
+    pub fn query_all(sql: &mut Sql, statement: &str) -> Result<Array, Box<EvalAltResult>> { query_params(sql, statement, Dynamic::UNIT) }
+
+    #[rhai_fn(global, return_raw, name = "query")]
+    pub fn query_params(sql: &mut Sql, statement: &str, params: Dynamic) -> Result<Array, Box<EvalAltResult>> { query(&*connection(sql)?, statement, &params) }
+
+    #[rhai_fn(global, return_raw, name = "execute")]
+    pub fn execute_all(sql: &mut Sql, statement: &str) -> Result<INT, Box<EvalAltResult>> { execute_params(sql, statement, Dynamic::UNIT) }
+
+    #[rhai_fn(global, return_raw, name = "execute")]
+    pub fn execute_params(sql: &mut Sql, statement: &str, params: Dynamic) -> Result<INT, Box<EvalAltResult>> { execute(&*connection(sql)?, statement, &params) }
+
+    #[rhai_fn(global, return_raw, name = "transaction")]
+    pub fn transaction_with(context: NativeCallContext, sql: &mut Sql, callback: FnPtr) -> Result<Dynamic, Box<EvalAltResult>> { transaction(&context, sql, callback) }
+
+    #[rhai_fn(global, return_raw, name = "query")]
+    pub fn tx_query(tx: &mut SqlTransaction, statement: &str) -> Result<Array, Box<EvalAltResult>> { tx_query_params(tx, statement, Dynamic::UNIT) }
+
+    #[rhai_fn(global, return_raw, name = "query")]
+    pub fn tx_query_params(tx: &mut SqlTransaction, statement: &str, params: Dynamic) -> Result<Array, Box<EvalAltResult>> { with_transaction(tx, |conn| query(conn, statement, &params)) }
+
+    #[rhai_fn(global, return_raw, name = "execute")]
+    pub fn tx_execute(tx: &mut SqlTransaction, statement: &str) -> Result<INT, Box<EvalAltResult>> { tx_execute_params(tx, statement, Dynamic::UNIT) }
+
+    #[rhai_fn(global, return_raw, name = "execute")]
+    pub fn tx_execute_params(tx: &mut SqlTransaction, statement: &str, params: Dynamic) -> Result<INT, Box<EvalAltResult>> { with_transaction(tx, |conn| execute(conn, statement, &params)) }
+}
@@ -1,15 +1,35 @@
-use crate::structs::config::Config;
+use crate::{helpers::prelude::*, modules, routes, structs::config::Config};
+use colored::Colorize;
+use macros_rs::fmt::crashln;
 use global_placeholders::init;
 use macros_rs::fs::{file_exists, folder_exists};
 use panic::setup_panic;
-use std::fs::create_dir_all;
+use std::{fs::create_dir_all, path::Path, sync::OnceLock};
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// The config loaded at startup, for code that runs inside scripts.
+pub fn config() -> Option<&'static Config> { CONFIG.get() }
 
 pub fn init(cli: &crate::Cli) -> Config {
     if !file_exists!(&cli.config) {
-        Config::new().set_path(&format!("{}.tmp", cli.config)).write_example()
+        crashln!("{FAIL} Failed to find config file {}\n\nRun {} to create a project here, or {} for a new directory.", cli.config.white(), "script init".cyan(), "script new <dir>".cyan());
+    }
+
+    let env_file = match &cli.env_file {
+        Some(path) => path.to_owned(),
+        None => Path::new(&cli.config).with_file_name(".env"),
+    };
+
+    // variables already set in the environment take precedence
+    match dotenvy::from_path(&env_file) {
+        Ok(_) => log::debug!(path = env_file.to_str(), "loaded env file"),
+        Err(err) if err.not_found() && cli.env_file.is_none() => {}
+        Err(err) => crashln!("{FAIL} Failed to load {}, {err}", env_file.display()),
     }
 
-    let mut config = Config::new().set_path(&cli.config).read();
+    let mut config = Config::from_cli(cli);
+    modules::env::load(config.env.to_owned().unwrap_or_default());
 
     if let Some(port) = cli.port.to_owned() {
         config.override_port(port)
@@ -23,11 +43,19 @@ pub fn init(cli: &crate::Cli) -> Config {
         config.override_address(address)
     }
 
-    let cache_dir = format!("{}/cache", config.settings.cache);
+    let _ = CONFIG.set(config.clone());
+
+    if config.memory_cache() {
+        routes::cache::use_memory();
+    } else {
+        let cache_dir = format!("{}/cache", config.settings.cache);
 
-    if !folder_exists!(&cache_dir) {
-        create_dir_all(&cache_dir).unwrap();
-        log::info!("created cached dir");
+        if !folder_exists!(&cache_dir) {
+            match create_dir_all(&cache_dir) {
+                Ok(_) => log::info!("created cached dir"),
+                Err(err) => routes::cache::downgrade(err),
+            }
+        }
     }
 
     init!("base.cache", config.settings.cache);
@@ -37,6 +65,11 @@ pub fn init(cli: &crate::Cli) -> Config {
     init!("dirs.handler", format!("{}/handler{{}}.r", config.settings.cache));
     init!("dirs.cache.index", format!("{}/routes.toml", config.settings.cache));
     init!("dirs.cache.hash", format!("{}/hashes.toml", config.settings.cache));
+    init!("dirs.history", format!("{}/history", config.settings.cache));
+
+    if !routes::cache::is_memory() {
+        routes::cache::load(config.settings.cache_format);
+    }
 
     setup_panic! {
         name: "Script Web Engine",
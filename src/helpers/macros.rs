@@ -16,14 +16,7 @@ macro_rules! send {
 #[macro_export]
 macro_rules! error {
     ($req:ident->$err:ident@$url:expr) => {{
-        let body = Message {
-            error: "Function Not Found",
-            code: StatusCode::NOT_FOUND.as_u16(),
-            message: format!("Have you created the <code>{}</code> route?", $url),
-            note: "You can add <code>* {}</code> or <code>404 {}</code> routes as well",
-        };
-
         log::error!(err = $err.to_string(), "Error finding route");
-        send!($req->(body.render().unwrap(), ContentType::html(), StatusCode::NOT_FOUND))
+        send!($req->$crate::http::not_found(&$url.to_string()))
     }};
 }
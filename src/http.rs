@@ -2,19 +2,19 @@ use crate::{
     helpers,
     helpers::prelude::*,
     modules::prelude::*,
-    routes::prelude::*,
-    structs::{config::*, template::*},
+    routes::{prelude::*, Route},
+    structs::{config::*, modules::{MongoCursor, Sql, SqlTransaction}, template::*},
 };
 
 use mime::Mime;
 use reqwest::blocking::Client as ReqwestClient;
 use rhai_dynamic::ToDynamic;
-use std::{collections::HashMap, io, sync::Arc};
+use std::{io, sync::Arc};
 
 use rhai::{exported_module as export, plugin::*, Dynamic, Engine, Map, Scope};
 
 use actix_web::{
-    http::{header::ContentType, StatusCode, Uri},
+    http::{header::ContentType, StatusCode},
     web::{self, Data},
     App, HttpRequest, HttpResponse, HttpServer, Responder,
 };
@@ -35,22 +35,6 @@ pub fn response(data: String, content_type: String, status_code: i64) -> (String
     (data, content_type, helpers::convert_status(status_code))
 }
 
-fn parse_bool(s: &str) -> bool {
-    match s.trim().to_lowercase().as_str() {
-        "true" | "1" | "yes" | "on" => true,
-        _ => false,
-    }
-}
-
-fn parse_slash(s: &str) -> String {
-    let parts: Vec<&str> = s.splitn(3, '/').collect();
-    if parts.len() > 1 {
-        format!("{}/{}", parts[0], parts[1])
-    } else {
-        s.to_string()
-    }
-}
-
 pub fn proxy(url: String) -> (String, ContentType, StatusCode) {
     let client = ReqwestClient::new();
     let response = match client.get(url).send() {
@@ -76,80 +60,124 @@ pub fn proxy(url: String) -> (String, ContentType, StatusCode) {
     }
 }
 
-struct Handler<'h> {
-    url: &'h Uri,
-    path: &'h str,
-    engine: &'h mut Engine,
-    scope: &'h mut Scope<'h>,
+pub type Response = (String, ContentType, StatusCode);
+
+/// Why a request produced no response from the workers.
+pub enum Failure {
+    NotFound(String),
+    Runtime(String),
+}
+
+#[derive(Clone)]
+pub struct Request {
+    pub path: String,
+    pub url: String,
+    pub method: String,
+    pub version: String,
+    pub query: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
 }
 
-impl<'h> Handler<'h> {
-    fn url(&self) -> String { self.url.to_string() }
+#[derive(Clone, ToDynamic)]
+struct Internal {
+    version: &'static str,
 }
 
-async fn handler(req: HttpRequest, config: Data<Arc<Config>>) -> Result<impl Responder, actix_web::Error> {
-    let mut modules = Modules::new();
+impl Request {
+    fn from_http(req: &HttpRequest, body: &web::Bytes) -> Self {
+        let headers = req
+            .headers()
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_str().unwrap_or_default().to_string()))
+            .collect();
+
+        Request {
+            headers,
+            url: req.uri().to_string(),
+            path: req.path().to_owned(),
+            method: req.method().to_string(),
+            version: format!("{:?}", req.version()),
+            query: req.query_string().to_string(),
+            body: String::from_utf8_lossy(body).to_string(),
+        }
+    }
 
-    let app = Handler {
-        url: req.uri(),
-        path: req.path(),
-        engine: &mut Engine::new(),
-        scope: &mut Scope::new(),
-    };
+    fn to_dynamic(&self) -> Dynamic {
+        let mut map = Map::new();
+        let headers: Map = self.headers.iter().map(|(name, value)| (name.into(), value.into())).collect();
 
-    modules.builtin(app.engine);
+        map.insert("path".into(), self.path.to_owned().into());
+        map.insert("url".into(), self.url.to_owned().into());
+        map.insert("method".into(), self.method.to_owned().into());
+        map.insert("version".into(), self.version.to_owned().into());
+        map.insert("query".into(), self.query.to_owned().into());
+        map.insert("headers".into(), headers.into());
+        map.insert("body".into(), self.body.to_owned().into());
+
+        map.into()
+    }
+
+    /// Builds a request for `url` without a client, as used by the cli.
+    pub fn synthetic(method: &str, url: &str, headers: Vec<(String, String)>, body: String) -> Self {
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+
+        Request {
+            headers,
+            body,
+            url: url.to_string(),
+            path: path.to_string(),
+            method: method.to_uppercase(),
+            version: "HTTP/1.1".into(),
+            query: query.to_string(),
+        }
+    }
+}
+
+/// Creates an engine with every module available to route handlers.
+pub fn engine(config: &Config) -> Engine {
+    let mut engine = Engine::new();
+    let mut modules = Modules::new();
+
+    modules.builtin(&mut engine);
 
     modules.register("cmd", export!(cmd));
+    modules.register("env", export!(env_vars));
     modules.register("tar", export!(tar));
     modules.register("json", export!(json));
     modules.register("http", export!(http));
     modules.register("exists", export!(exists));
 
     modules.get_ext().for_each(|ext| {
-        app.engine.register_static_module(ext.0, ext.1);
+        engine.register_static_module(ext.0, ext.1);
     });
 
     if let Some(database) = &config.database {
         if let Some(_) = &database.kv {
             let kv = exported_module!(kv_db);
-            app.engine.register_static_module("kv", kv.into());
+            engine.register_static_module("kv", kv.into());
         }
         if let Some(_) = &database.mongo {
-            let mongo = exported_module!(mongo_db);
-            app.engine.register_static_module("mongo", mongo.into());
+            let mut mongo = exported_module!(mongo_db);
+            mongo.set_custom_type::<mongodb::bson::oid::ObjectId>("ObjectId");
+            mongo.set_custom_type::<mongodb::bson::DateTime>("Date");
+            mongo.set_custom_type::<MongoCursor>("Cursor");
+            mongo.set_iterator_result::<MongoCursor, Map>();
+            engine.register_static_module("mongo", mongo.into());
         }
         if let Some(_) = &database.redis {
             let redis = exported_module!(redis_db);
-            app.engine.register_static_module("redis", redis.into());
+            engine.register_static_module("redis", redis.into());
+        }
+        if database.sqlite.is_some() {
+            let mut sql = exported_module!(sql_db);
+            sql.set_custom_type::<Sql>("Sql");
+            sql.set_custom_type::<SqlTransaction>("Transaction");
+            engine.register_static_module("sql", sql.into());
         }
     }
 
-    #[derive(Clone, ToDynamic)]
-    struct Request {
-        path: String,
-        url: String,
-        version: String,
-        query: String,
-    }
-
-    #[derive(Clone, ToDynamic)]
-    struct Internal {
-        version: &'static str,
-    }
-
-    let request = Request {
-        url: app.url.to_string(),
-        path: app.path.to_owned(),
-        version: format!("{:?}", req.version()),
-        query: req.query_string().to_string(),
-    };
-
-    let internal = Internal { version: env!("CARGO_PKG_VERSION") };
-
-    app.scope.push("app", internal.to_dynamic());
-    app.scope.push("request", request.to_dynamic());
-
-    app.engine
+    engine
         .register_fn("cwd", cwd)
         .register_fn("proxy", proxy)
         .register_fn("response", response)
@@ -164,52 +192,56 @@ async fn handler(req: HttpRequest, config: Data<Arc<Config>>) -> Result<impl Res
         .register_fn("join", array::join_separator)
         .register_fn("repeat", string::repeat);
 
-    let contents = get_workers(&config.workers).await?;
+    engine
+}
 
-    if let Err(err) = parse::try_parse(&contents).await {
-        error!(req->err@app.url);
+/// Creates the scope shared by every handler, exposing `app` and `env`.
+pub fn globals() -> Scope<'static> {
+    let mut scope = Scope::new();
+    let internal = Internal { version: env!("CARGO_PKG_VERSION") };
+
+    scope.push("app", internal.to_dynamic());
+    scope.push("env", crate::modules::env::table());
+
+    scope
+}
+
+/// Creates the scope a route handler runs in, the globals and `request`.
+pub fn scope(request: &Request) -> Scope<'static> {
+    let mut scope = globals();
+    scope.push("request", request.to_dynamic());
+    scope
+}
+
+pub fn not_found(url: &str) -> Response {
+    let body = Message {
+        error: "Function Not Found",
+        code: StatusCode::NOT_FOUND.as_u16(),
+        message: format!("Have you created the <code>{url}</code> route?"),
+        note: "You can add <code>* {}</code> or <code>404 {}</code> routes as well",
     };
 
-    let (route, args) = match Route::get(parse_slash(&app.url())).await {
-        Ok(route) => {
-            let mut matched_url = app.url();
-
-            let cfg = match route.cfg {
-                Some(cfg) => cfg,
-                None => HashMap::new(),
-            };
-
-            for (item, val) in cfg {
-                // convert to enum Cfg::Wildcard, etc
-                match item.as_str() {
-                    "wildcard" => {
-                        if parse_slash(&app.url()) == route.route && parse_bool(&val) {
-                            matched_url = parse_slash(&app.url());
-                            break;
-                        }
-                    }
-                    _ => {}
-                }
-            }
-
-            match Route::get(matched_url.to_owned()).await {
-                Ok(matched) => (matched, vec![]),
-                Err(err) => match Route::search_for(matched_url).await {
-                    Some(matched) => matched,
-                    None => error!(req->err@app.url),
-                },
-            }
-        }
-        Err(err) => match Route::search_for(app.url()).await {
-            Some(matched) => matched,
-            None => error!(req->err@app.url),
-        },
+    (body.render().unwrap(), ContentType::html(), StatusCode::NOT_FOUND)
+}
+
+pub fn server_error(err: &str) -> Response {
+    let body = ServerError {
+        error: err.replace("\n", "<br>"),
+        context: vec![],
     };
 
-    let mut ast = match app.engine.compile(route.construct_fn()) {
+    (body.render().unwrap(), ContentType::html(), StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Runs a resolved route synchronously, blocking clients such as `http::get` need this.
+pub fn run(config: &Config, request: &Request, route: Route, args: Vec<String>) -> Result<Response, Failure> {
+    let engine = engine(config);
+    let mut scope = scope(request);
+
+    let mut ast = match engine.compile(route.construct_fn()) {
         Ok(ast) => ast,
         // fix fn name error
-        Err(err) => helpers::error(&app.engine, &app.url(), err),
+        Err(err) => helpers::error(&engine, &request.url, err),
     };
 
     ast.set_source("runtime::workers");
@@ -219,22 +251,42 @@ async fn handler(req: HttpRequest, config: Data<Arc<Config>>) -> Result<impl Res
         name => name,
     };
 
-    match app.engine.call_fn::<(String, ContentType, StatusCode)>(app.scope, &ast, fn_name, args) {
-        Ok(response) => send!(req->response),
-        Err(err) => {
-            let body = ServerError {
-                error: err.to_string().replace("\n", "<br>"),
-                context: vec![],
-            };
+    engine.call_fn::<Response>(&mut scope, &ast, fn_name, args).map_err(|err| Failure::Runtime(err.to_string()))
+}
 
-            return Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).content_type(ContentType::html()).body(body.render().unwrap()));
-        }
+/// Resolves and runs the route for `request`, the same way the server does.
+pub async fn invoke(config: Arc<Config>, request: Request) -> Result<Response, Failure> {
+    let contents = get_workers(&config.workers).await.map_err(|err| Failure::Runtime(err.to_string()))?;
+
+    if let Err(err) = parse::try_parse(&contents).await {
+        return Err(Failure::NotFound(err.to_string()));
+    };
+
+    let resolved = match resolve(&request.path).await {
+        Some(resolved) => resolved,
+        None => return Err(Failure::NotFound(format!("no route matches {}", request.path))),
+    };
+
+    match tokio::task::spawn_blocking(move || run(&config, &request, resolved.route, resolved.args)).await {
+        Ok(result) => result,
+        Err(err) => Err(Failure::Runtime(err.to_string())),
+    }
+}
+
+async fn handler(req: HttpRequest, body: web::Bytes, config: Data<Arc<Config>>) -> Result<impl Responder, actix_web::Error> {
+    let request = Request::from_http(&req, &body);
+
+    match invoke(Arc::clone(&config), request).await {
+        Ok(response) => send!(req->response),
+        Err(Failure::NotFound(err)) => error!(req->err@req.uri()),
+        Err(Failure::Runtime(err)) => send!(req->server_error(&err)),
     };
 }
 
 #[tokio::main]
 pub async fn start(config: Config) -> io::Result<()> {
     let owned = Arc::new(config.to_owned());
+    crate::database::pubsub::start(&owned);
 
     let app = move || {
         let config = Arc::clone(&owned);
@@ -0,0 +1,393 @@
+pub mod docs;
+
+use crate::{
+    routes::{
+        lint::{self, Problem, Severity, Source},
+        parse,
+    },
+    structs::config::Config,
+};
+
+use docs::{Doc, DOCS, MODULES};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::{collections::HashMap, error::Error, fs, path::PathBuf};
+
+use lsp_types::{
+    notification::{DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument, LogMessage, Notification as _, PublishDiagnostics},
+    request::{Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, Request as _},
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse, Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams,
+    DidCloseTextDocumentParams, DidOpenTextDocumentParams, DidSaveTextDocumentParams, DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse, Documentation, GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability, Location,
+    LogMessageParams, MarkupContent, MarkupKind, MessageType, OneOf, Position, PublishDiagnosticsParams, Range, SaveOptions, ServerCapabilities, SymbolKind, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextDocumentSyncOptions, TextDocumentSyncSaveOptions, Url,
+};
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+fn params<P: DeserializeOwned>(params: Value) -> std::result::Result<P, (ErrorCode, String)> { serde_json::from_value(params).map_err(|err| (ErrorCode::InvalidParams, err.to_string())) }
+
+struct Server {
+    config: Config,
+    connection: Connection,
+    documents: HashMap<Url, String>,
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Options(TextDocumentSyncOptions {
+            open_close: Some(true),
+            change: Some(TextDocumentSyncKind::FULL),
+            save: Some(TextDocumentSyncSaveOptions::SaveOptions(SaveOptions { include_text: Some(true) })),
+            ..Default::default()
+        })),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![":".into(), ".".into()]),
+            ..Default::default()
+        }),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    }
+}
+
+fn is_ident(c: char) -> bool { c.is_ascii_alphanumeric() || c == '_' }
+
+/// The identifier at the end of `text`.
+fn trailing_ident(text: &str) -> String {
+    let start = text.rfind(|c: char| !is_ident(c)).map_or(0, |i| i + 1);
+    text[start..].to_string()
+}
+
+fn line_range(text: &str, line: usize) -> Range {
+    let length = text.lines().nth(line).map(|line| line.chars().count()).unwrap_or(0);
+    Range::new(Position::new(line as u32, 0), Position::new(line as u32, length as u32))
+}
+
+/// The `module::name` or `name` under the cursor.
+fn word_at(text: &str, position: Position) -> Option<(Option<String>, String)> {
+    let line: Vec<char> = text.lines().nth(position.line as usize)?.chars().collect();
+    let cursor = (position.character as usize).min(line.len());
+
+    let start = line[..cursor].iter().rposition(|c| !is_ident(*c)).map_or(0, |i| i + 1);
+    let end = line[cursor..].iter().position(|c| !is_ident(*c)).map_or(line.len(), |i| cursor + i);
+    let name: String = line[start..end].iter().collect();
+
+    if name.is_empty() {
+        return None;
+    }
+
+    let before: String = line[..start].iter().collect();
+    let module = before.strip_suffix("::").map(trailing_ident);
+
+    Some((module, name))
+}
+
+fn markdown(value: String) -> HoverContents { HoverContents::Markup(MarkupContent { kind: MarkupKind::Markdown, value }) }
+
+fn completion(doc: &Doc) -> CompletionItem {
+    CompletionItem {
+        label: doc.name.into(),
+        kind: Some(match doc.method {
+            true => CompletionItemKind::METHOD,
+            false if doc.signature.contains('(') => CompletionItemKind::FUNCTION,
+            false => CompletionItemKind::VARIABLE,
+        }),
+        detail: Some(doc.signature.into()),
+        documentation: Some(Documentation::String(doc.doc.into())),
+        ..Default::default()
+    }
+}
+
+fn diagnostic(text: &str, problem: &Problem) -> Diagnostic {
+    Diagnostic {
+        range: line_range(text, problem.line.saturating_sub(1)),
+        severity: Some(match problem.severity {
+            Severity::Error => DiagnosticSeverity::ERROR,
+            Severity::Warning => DiagnosticSeverity::WARNING,
+        }),
+        source: Some(env!("CARGO_PKG_NAME").into()),
+        message: problem.message.to_owned(),
+        ..Default::default()
+    }
+}
+
+impl Server {
+    fn text(&self, uri: &Url) -> String {
+        match self.documents.get(uri) {
+            Some(text) => text.to_owned(),
+            None => uri.to_file_path().ok().and_then(|path| fs::read_to_string(path).ok()).unwrap_or_default(),
+        }
+    }
+
+    fn path(uri: &Url) -> PathBuf { uri.to_file_path().unwrap_or_else(|_| PathBuf::from(uri.path())) }
+
+    /// Routes of every worker, using unsaved editor contents where available.
+    fn sources(&self) -> Vec<(Url, Source)> {
+        let mut uris: Vec<Url> = self.config.workers.iter().filter_map(|worker| fs::canonicalize(worker).ok()).filter_map(|path| Url::from_file_path(path).ok()).collect();
+
+        for uri in self.documents.keys() {
+            if !uris.contains(uri) {
+                uris.push(uri.to_owned());
+            }
+        }
+
+        uris.into_iter()
+            .flat_map(|uri| {
+                let sources = lint::parse_file(Server::path(&uri), &self.text(&uri)).unwrap_or_default();
+                sources.into_iter().map(move |source| (uri.to_owned(), source))
+            })
+            .collect()
+    }
+
+    fn send(&self, message: impl Into<Message>) -> Result<()> {
+        self.connection.sender.send(message.into())?;
+        Ok(())
+    }
+
+    fn publish(&self, uri: Url) -> Result<()> {
+        let text = self.text(&uri);
+        let problems = match lint::parse_file(Server::path(&uri), &text) {
+            Ok(sources) => lint::lint(&sources),
+            Err(problem) => vec![problem],
+        };
+
+        let params = PublishDiagnosticsParams {
+            uri,
+            version: None,
+            diagnostics: problems.iter().map(|problem| diagnostic(&text, problem)).collect(),
+        };
+
+        self.send(Notification::new(PublishDiagnostics::METHOD.into(), params))
+    }
+
+    fn complete(&self, params: CompletionParams) -> Vec<CompletionItem> {
+        let position = params.text_document_position.position;
+        let text = self.text(&params.text_document_position.text_document.uri);
+        let line = text.lines().nth(position.line as usize).unwrap_or_default();
+        let before: String = line.chars().take(position.character as usize).collect();
+        let before = before.trim_end_matches(is_ident);
+
+        if let Some(prefix) = before.strip_suffix("::") {
+            let module = trailing_ident(prefix);
+            return DOCS.iter().filter(|doc| !doc.method && doc.module == Some(module.as_str())).map(completion).collect();
+        }
+
+        if before.ends_with('.') {
+            let mut items: Vec<CompletionItem> = Vec::new();
+            for doc in DOCS.iter().filter(|doc| doc.method) {
+                if !items.iter().any(|item| item.label == doc.name) {
+                    items.push(completion(doc));
+                }
+            }
+            return items;
+        }
+
+        let modules = MODULES.iter().map(|(name, doc)| CompletionItem {
+            label: name.to_string(),
+            kind: Some(CompletionItemKind::MODULE),
+            documentation: Some(Documentation::String(doc.to_string())),
+            ..Default::default()
+        });
+
+        modules.chain(DOCS.iter().filter(|doc| doc.module.is_none()).map(completion)).collect()
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let position = params.text_document_position_params;
+        let (module, name) = word_at(&self.text(&position.text_document.uri), position.position)?;
+
+        let found = docs::lookup(module.as_deref(), &name);
+        if !found.is_empty() {
+            let value = found.iter().map(|doc| format!("```rhai\n{}\n```\n{}", doc.signature, doc.doc)).collect::<Vec<_>>().join("\n\n---\n\n");
+            return Some(Hover { contents: markdown(value), range: None });
+        }
+
+        if let Some(doc) = docs::module(&name) {
+            return Some(Hover {
+                contents: markdown(format!("```rhai\nmod {name}\n```\n{doc}")),
+                range: None,
+            });
+        }
+
+        let (_, source) = self.sources().into_iter().find(|(_, source)| source.route.fn_name == name)?;
+        let args = source.route.args.to_owned().unwrap_or_default().join(", ");
+
+        Some(Hover {
+            contents: markdown(format!("```rhai\n{}({args})\n```\nserves `{}` ({})", source.route.fn_name, source.route.route, source.location())),
+            range: None,
+        })
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let (module, name) = word_at(&self.text(&position.text_document.uri), position.position)?;
+
+        if module.is_some() {
+            return None;
+        }
+
+        let locations: Vec<Location> = self
+            .sources()
+            .into_iter()
+            .filter(|(_, source)| source.route.fn_name == name)
+            .map(|(uri, source)| {
+                let range = line_range(&self.text(&uri), source.route.start_pos);
+                Location::new(uri, range)
+            })
+            .collect();
+
+        match locations.len() {
+            0 => None,
+            1 => Some(GotoDefinitionResponse::Scalar(locations[0].to_owned())),
+            _ => Some(GotoDefinitionResponse::Array(locations)),
+        }
+    }
+
+    #[allow(deprecated)]
+    fn outline(&self, params: DocumentSymbolParams) -> Vec<DocumentSymbol> {
+        let uri = params.text_document.uri;
+        let text = self.text(&uri);
+        let sources = lint::parse_file(Server::path(&uri), &text).unwrap_or_default();
+        let cases = parse::cases(&text).unwrap_or_default();
+        let subscriptions = parse::subscriptions(&text).unwrap_or_default();
+
+        let routes = sources.into_iter().map(|source| {
+            let mut range = line_range(&text, source.route.start_pos);
+            range.end = line_range(&text, source.route.end_pos).end;
+
+            DocumentSymbol {
+                range,
+                name: source.route.route.to_string(),
+                detail: Some(source.route.fn_name.to_string()),
+                kind: SymbolKind::FUNCTION,
+                selection_range: line_range(&text, source.route.start_pos),
+                tags: None,
+                deprecated: None,
+                children: None,
+            }
+        });
+
+        let tests = cases.into_iter().map(|case| DocumentSymbol {
+            name: format!("test \"{}\"", case.name),
+            detail: None,
+            kind: SymbolKind::METHOD,
+            range: line_range(&text, case.line - 1),
+            selection_range: line_range(&text, case.line - 1),
+            tags: None,
+            deprecated: None,
+            children: None,
+        });
+
+        let subscriptions = subscriptions.into_iter().map(|subscription| DocumentSymbol {
+            name: format!("subscribe \"{}\"", subscription.channel),
+            detail: Some(subscription.route.fn_name.to_string()),
+            kind: SymbolKind::EVENT,
+            range: line_range(&text, subscription.line - 1),
+            selection_range: line_range(&text, subscription.line - 1),
+            tags: None,
+            deprecated: None,
+            children: None,
+        });
+
+        let mut symbols: Vec<DocumentSymbol> = routes.chain(tests).chain(subscriptions).collect();
+        symbols.sort_by_key(|symbol| symbol.range.start.line);
+        symbols
+    }
+
+    /// The result of one request, or the error code and message to answer it with.
+    fn respond(&self, req: Request) -> std::result::Result<Value, (ErrorCode, String)> {
+        let result = match req.method.as_str() {
+            Completion::METHOD => serde_json::to_value(CompletionResponse::Array(self.complete(params(req.params)?))),
+            HoverRequest::METHOD => serde_json::to_value(self.hover(params(req.params)?)),
+            GotoDefinition::METHOD => serde_json::to_value(self.definition(params(req.params)?)),
+            DocumentSymbolRequest::METHOD => serde_json::to_value(DocumentSymbolResponse::Nested(self.outline(params(req.params)?))),
+            method => return Err((ErrorCode::MethodNotFound, format!("unsupported request {method}"))),
+        };
+
+        result.map_err(|err| (ErrorCode::InternalError, err.to_string()))
+    }
+
+    /// Answers every request, so a malformed one fails on its own instead of stopping the server.
+    fn request(&self, req: Request) -> Result<()> {
+        let id = req.id.clone();
+
+        match self.respond(req) {
+            Ok(result) => self.send(Response::new_ok(id, result)),
+            Err((code, message)) => self.send(Response::new_err(id, code as i32, message)),
+        }
+    }
+
+    fn notification(&mut self, not: Notification) -> Result<()> {
+        match not.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams = serde_json::from_value(not.params)?;
+                self.documents.insert(params.text_document.uri.to_owned(), params.text_document.text);
+                self.publish(params.text_document.uri)?;
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams = serde_json::from_value(not.params)?;
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.documents.insert(params.text_document.uri, change.text);
+                }
+            }
+            DidSaveTextDocument::METHOD => {
+                let params: DidSaveTextDocumentParams = serde_json::from_value(not.params)?;
+                if let Some(text) = params.text {
+                    self.documents.insert(params.text_document.uri.to_owned(), text);
+                }
+                self.publish(params.text_document.uri)?;
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams = serde_json::from_value(not.params)?;
+                self.documents.remove(&params.text_document.uri);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<()> {
+        while let Ok(message) = self.connection.receiver.recv() {
+            match message {
+                Message::Request(req) => {
+                    if self.connection.handle_shutdown(&req)? {
+                        return Ok(());
+                    }
+                    self.request(req)?;
+                }
+                Message::Notification(not) => {
+                    // notifications get no reply, so a malformed one is reported to the client log and skipped
+                    let method = not.method.to_owned();
+                    if let Err(err) = self.notification(not) {
+                        self.send(Notification::new(LogMessage::METHOD.into(), LogMessageParams { typ: MessageType::WARNING, message: format!("{method} failed, {err}") }))?;
+                    }
+                }
+                Message::Response(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Serves the language server protocol over stdin and stdout.
+pub fn start(config: Config) -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+
+    connection.initialize(serde_json::to_value(capabilities())?)?;
+
+    let mut server = Server {
+        config,
+        connection,
+        documents: HashMap::new(),
+    };
+
+    server.run()?;
+    drop(server);
+    io_threads.join()?;
+
+    Ok(())
+}
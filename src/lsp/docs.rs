@@ -0,0 +1,190 @@
+/// A built-in function or value as shown in completion and hover.
+pub struct Doc {
+    pub module: Option<&'static str>,
+    pub name: &'static str,
+    pub signature: &'static str,
+    pub doc: &'static str,
+    /// Called on a value, like `db.get(key)`, instead of through the module.
+    pub method: bool,
+}
+
+const fn function(module: &'static str, name: &'static str, signature: &'static str, doc: &'static str) -> Doc {
+    Doc {
+        module: Some(module),
+        name,
+        signature,
+        doc,
+        method: false,
+    }
+}
+
+const fn method(module: &'static str, name: &'static str, signature: &'static str, doc: &'static str) -> Doc {
+    Doc {
+        module: Some(module),
+        name,
+        signature,
+        doc,
+        method: true,
+    }
+}
+
+const fn global(name: &'static str, signature: &'static str, doc: &'static str) -> Doc {
+    Doc {
+        module: None,
+        name,
+        signature,
+        doc,
+        method: false,
+    }
+}
+
+pub const MODULES: [(&str, &str); 10] = [
+    ("kv", "Embedded transactional key/value store, needs `[database.kv]`"),
+    ("mongo", "MongoDB client, needs `[database.mongo]`"),
+    ("redis", "Redis client, needs `[database.redis]`"),
+    ("sql", "SQLite database, needs `[database.sqlite]`"),
+    ("env", "Values from `[env]` and the process environment"),
+    ("http", "Outgoing http requests"),
+    ("json", "Convert between json strings and maps"),
+    ("cmd", "Run shell commands"),
+    ("tar", "Create and extract .tar.gz archives"),
+    ("exists", "Check for files and folders"),
+];
+
+pub const DOCS: &[Doc] = &[
+    global("request", "request: #{ path, url, method, version, query, headers, body }", "The incoming request."),
+    global("app", "app: #{ version }", "Information about the running server."),
+    global("env", "env: Map", "The `[env]` table of the config."),
+    global("text", "text(body: String, status?: int)", "Responds with plain text, the status defaults to 200."),
+    global("html", "html(body: String, status?: int)", "Responds with html, the status defaults to 200."),
+    global("json", "json(object: Dynamic, status?: int)", "Responds with the object serialized as json, the status defaults to 200."),
+    global("response", "response(data: String, type: String, status: int)", "Responds with a custom content type such as `xml`, `png` or `stream`."),
+    global("proxy", "proxy(url: String)", "Responds with the body, content type and status of another url."),
+    global("cwd", "cwd() -> String", "The current working directory."),
+    global("pad", "array.pad(count: int, value: Dynamic) -> Array", "Pads an array to `count` items with `value`."),
+    global("join", "array.join(separator?: String) -> String", "Joins the items of an array into a string."),
+    global("repeat", "string.repeat(count: int) -> String", "Repeats a string `count` times."),
+    function("kv", "load", "kv::load(path: String) -> KV", "Opens the store at `path`, creating it when missing."),
+    method("kv", "set", "db.set(key: String, value: Dynamic, ttl?: int)", "Stores any value under `key`, expiring after `ttl` seconds when given."),
+    method("kv", "get", "db.get(key: String) -> Dynamic", "The value under `key`, or `()` when it is missing or expired."),
+    method("kv", "del", "db.del(key: String) -> bool", "Removes `key`, returns whether it existed."),
+    method("kv", "exists", "db.exists(key: String) -> bool", "Whether `key` is set."),
+    method("kv", "expire", "db.expire(key: String, ttl: int) -> bool", "Expires `key` after `ttl` seconds, returns whether it exists."),
+    method("kv", "ttl", "db.ttl(key: String) -> int", "Seconds until `key` expires, -1 without a ttl and -2 when missing."),
+    method("kv", "incr", "db.incr(key: String, by?: int) -> int", "Atomically adds `by` (default 1) to an integer, starting from 0."),
+    method("kv", "compare_and_swap", "db.compare_and_swap(key: String, expected: Dynamic, new: Dynamic) -> bool", "Sets `new` only when the value still equals `expected`, `()` matches a missing key. An existing key keeps its ttl."),
+    method("kv", "scan", "db.scan(prefix: String) -> Array", "Entries whose key starts with `prefix` as `#{key, value}`, in key order."),
+    method("kv", "list", "db.list() -> Array", "Every key in the store."),
+    method("kv", "count", "db.count() -> int", "Number of keys in the store."),
+    method("kv", "drop", "db.drop()", "Releases the handle, the store itself stays open and shared."),
+    function("mongo", "connect", "mongo::connect(name?: String) -> Client", "The shared client for `[database.mongo]`, or `[database.mongo.<name>]`."),
+    function("mongo", "oid", "mongo::oid(hex?: String) -> ObjectId", "A new ObjectId, or one parsed from `hex`. Renders as its hex string in json."),
+    function("mongo", "date", "mongo::date(at?: int | String) -> Date", "Now, unix milliseconds or an RFC 3339 string. Renders as RFC 3339 in json; `.millis` reads it back."),
+    method("mongo", "db", "client.db(name: String) -> Database", "Selects a database."),
+    method("mongo", "shutdown", "client.shutdown()", "Closes the connection."),
+    method("mongo", "get", "db.get(name: String) -> Collection", "Selects an existing collection."),
+    method("mongo", "create", "db.create(name: String) -> Collection", "Creates a collection and selects it."),
+    method("mongo", "find", "collection.find(filter?: Map, options?: Map) -> Cursor", "Documents matching `filter`. Options: `sort`, `limit`, `skip`, `projection`, `batch_size`; sort by several keys with an array such as `[#{age: -1}, #{name: 1}]`."),
+    method("mongo", "find_one", "collection.find_one(filter: Map, options?: Map) -> Map", "The first document matching `filter`. Options: `sort`, `skip`, `projection`."),
+    method("mongo", "aggregate", "collection.aggregate(pipeline: Array, options?: Map) -> Cursor", "Runs an aggregation pipeline. Options: `allow_disk_use`, `batch_size`."),
+    method("mongo", "next", "cursor.next() -> Map", "The next document, or `()` once the cursor is done. Cursors also work in `for` loops and fetch in batches."),
+    method("mongo", "collect", "cursor.collect() -> Array", "Reads the remaining documents of a cursor."),
+    method("mongo", "count_documents", "collection.count_documents(filter: Map, options?: Map) -> int", "Number of documents matching `filter`. Options: `limit`, `skip`."),
+    method("mongo", "distinct", "collection.distinct(field: String, filter?: Map) -> Array", "Distinct values of `field`."),
+    method("mongo", "insert", "collection.insert(document: Map | Array) -> Map | Array", "Inserts one or many documents, returning `#{insertedId}` or the ids. Unsupported values raise an error."),
+    method("mongo", "update", "collection.update(query: Map, update: Map | Array, options?: Map) -> Map", "Applies `$set`, `$inc` and other operators, or a pipeline, to the first match; a plain map replaces it. Options: `upsert`."),
+    method("mongo", "update_many", "collection.update_many(query: Map, update: Map | Array, options?: Map) -> Map", "Applies update operators or a pipeline to every match. Options: `upsert`."),
+    method("mongo", "delete", "collection.delete(filter: Map)", "Deletes the first document matching `filter`."),
+    method("mongo", "delete_many", "collection.delete_many(filter: Map)", "Deletes every document matching `filter`."),
+    method("mongo", "count", "collection.count() -> int", "Number of documents, databases or collections."),
+    method("mongo", "list", "db.list() -> Array", "Names of the databases or collections."),
+    method("mongo", "drop", "collection.drop()", "Drops a collection or database."),
+    method("mongo", "create_index", "collection.create_index(keys: Map | Array, options?: Map) -> String", "Creates an index and returns its name. Options: `name`, `unique`, `sparse`, `expire_after` (seconds)."),
+    method("mongo", "indexes", "collection.indexes() -> Array", "Names of the indexes."),
+    method("mongo", "drop_index", "collection.drop_index(name: String)", "Drops an index."),
+    function("redis", "connect", "redis::connect(name?: String) -> Redis", "The connection pool for `[database.redis]`, or `[database.redis.<name>]`."),
+    method("redis", "set", "conn.set(key: String, value: String | int)", "Sets `key` to `value`."),
+    method("redis", "get", "conn.get(key: String) -> Dynamic", "The value of `key`, or `()` when missing."),
+    method("redis", "setex", "conn.setex(key: String, value: Dynamic, seconds: int)", "Sets `key` to expire after `seconds`."),
+    method("redis", "setnx", "conn.setnx(key: String, value: Dynamic) -> bool", "Sets `key` only when it is missing, returns whether it was set."),
+    method("redis", "mget", "conn.mget(keys: Array) -> Array", "The values of `keys`, `()` for missing ones."),
+    method("redis", "mset", "conn.mset(values: Map)", "Sets every key of `values`."),
+    method("redis", "del", "conn.del(key: String | Array) -> int", "Deletes keys, returns how many were removed."),
+    method("redis", "expire", "conn.expire(key: String, seconds: int) -> bool", "Expires `key` after `seconds`, returns whether it exists."),
+    method("redis", "persist", "conn.persist(key: String) -> bool", "Removes the expiry of `key`."),
+    method("redis", "ttl", "conn.ttl(key: String) -> int", "Seconds until `key` expires, -1 without an expiry and -2 when missing."),
+    method("redis", "rename", "conn.rename(key: String, new: String)", "Renames `key`."),
+    method("redis", "append", "conn.append(key: String, value: String) -> int", "Appends `value` to `key`, returns the new length."),
+    method("redis", "inc", "conn.inc(key: String, value: int) -> int", "Increments `key` by `value`, returns the result."),
+    method("redis", "dec", "conn.dec(key: String, value: int) -> int", "Decrements `key` by `value`, returns the result."),
+    method("redis", "exists", "conn.exists(key: String) -> bool", "Whether `key` exists."),
+    method("redis", "keys", "conn.keys(filter: String) -> Array", "Keys matching the glob `filter`."),
+    method("redis", "scan", "conn.scan(pattern: String) -> Array", "Keys matching the glob `pattern`, found without blocking the server."),
+    method("redis", "list", "conn.list(filter?: String) -> Map", "Keys and values, optionally matching `filter`."),
+    method("redis", "hset", "conn.hset(key: String, field: String, value: Dynamic) -> int", "Sets a hash field, or every field of a map, returns how many are new."),
+    method("redis", "hget", "conn.hget(key: String, field: String) -> String", "A hash field, or `()` when missing."),
+    method("redis", "hgetall", "conn.hgetall(key: String) -> Map", "Every field of a hash."),
+    method("redis", "hdel", "conn.hdel(key: String, field: String | Array) -> int", "Removes hash fields."),
+    method("redis", "hexists", "conn.hexists(key: String, field: String) -> bool", "Whether a hash field exists."),
+    method("redis", "hincr", "conn.hincr(key: String, field: String, by: int) -> int", "Increments a hash field."),
+    method("redis", "lpush", "conn.lpush(key: String, value: Dynamic | Array) -> int", "Pushes to the head of a list, returns its length."),
+    method("redis", "rpush", "conn.rpush(key: String, value: Dynamic | Array) -> int", "Pushes to the tail of a list, returns its length."),
+    method("redis", "lpop", "conn.lpop(key: String) -> String", "Pops the head of a list, `()` when empty."),
+    method("redis", "rpop", "conn.rpop(key: String) -> String", "Pops the tail of a list, `()` when empty."),
+    method("redis", "lrange", "conn.lrange(key: String, start: int, stop: int) -> Array", "Items of a list, negative indexes count from the end."),
+    method("redis", "llen", "conn.llen(key: String) -> int", "Length of a list."),
+    method("redis", "sadd", "conn.sadd(key: String, member: Dynamic | Array) -> int", "Adds set members, returns how many are new."),
+    method("redis", "srem", "conn.srem(key: String, member: Dynamic | Array) -> int", "Removes set members."),
+    method("redis", "smembers", "conn.smembers(key: String) -> Array", "Members of a set, sorted."),
+    method("redis", "sismember", "conn.sismember(key: String, member: Dynamic) -> bool", "Whether `member` is in the set."),
+    method("redis", "scard", "conn.scard(key: String) -> int", "Size of a set."),
+    method("redis", "zadd", "conn.zadd(key: String, member: Dynamic, score: float | int) -> int", "Adds a sorted set member with `score`."),
+    method("redis", "zrem", "conn.zrem(key: String, member: Dynamic | Array) -> int", "Removes sorted set members."),
+    method("redis", "zscore", "conn.zscore(key: String, member: Dynamic) -> float", "Score of `member`, `()` when missing."),
+    method("redis", "zincr", "conn.zincr(key: String, member: Dynamic, by: float | int) -> float", "Increments the score of `member`."),
+    method("redis", "zrange", "conn.zrange(key: String, start: int, stop: int) -> Array", "Members by rank, lowest score first."),
+    method("redis", "zrange_scores", "conn.zrange_scores(key: String, start: int, stop: int) -> Array", "Like `zrange`, as `#{member, score}` maps."),
+    method("redis", "zcard", "conn.zcard(key: String) -> int", "Size of a sorted set."),
+    method("redis", "command", "conn.command(name: String, args: Array) -> Dynamic", "Runs any redis command."),
+    method("redis", "pipeline", "conn.pipeline() -> Pipeline", "Queues commands to send in one round trip."),
+    method("redis", "multi", "conn.multi() -> Pipeline", "Like `pipeline`, wrapped in MULTI/EXEC."),
+    method("redis", "queue", "pipeline.queue(name: String, args?: Array)", "Adds a command to the pipeline."),
+    method("redis", "exec", "pipeline.exec() -> Array", "Sends the queued commands, returns their replies."),
+    function("redis", "publish", "redis::publish(channel: String, message: Dynamic) -> int", "Publishes on the default connection, returns how many subscribers received it."),
+    method("redis", "publish", "conn.publish(channel: String, message: Dynamic) -> int", "Publishes `message`, handled by `#[subscribe(channel)]` functions."),
+    method("redis", "xadd", "conn.xadd(key: String, fields: Map, maxlen?: int) -> String", "Appends an entry to a stream, returns its id."),
+    method("redis", "xlen", "conn.xlen(key: String) -> int", "Number of entries in a stream."),
+    method("redis", "xrange", "conn.xrange(key: String, start: String, end: String) -> Array", "Entries between two ids as `#{id, fields}`, `-` and `+` for the whole stream."),
+    method("redis", "xread", "conn.xread(key: String, id: String, count?: int) -> Array", "Entries after `id`, without blocking."),
+    method("redis", "xgroup_create", "conn.xgroup_create(key: String, group: String, id: String) -> bool", "Creates a consumer group, false when it already exists."),
+    method("redis", "xreadgroup", "conn.xreadgroup(key: String, group: String, consumer: String, count: int) -> Array", "Entries not yet delivered to the group."),
+    method("redis", "xack", "conn.xack(key: String, group: String, id: String | Array) -> int", "Acknowledges entries read with `xreadgroup`."),
+    function("sql", "connect", "sql::connect(name?: String) -> Sql", "The pooled database for `[database.sqlite]`, or `[database.sqlite.<name>]`. `sql::query`, `sql::execute` and `sql::transaction` use the unnamed one."),
+    method("sql", "query", "db.query(sql: String, params?: Array | Map | Dynamic) -> Array", "Rows as maps keyed by column. Params bind to `?` in order, a map binds to `:name` and must supply every placeholder."),
+    method("sql", "execute", "db.execute(sql: String, params?: Array | Map | Dynamic) -> int", "Runs a statement and returns the number of rows changed."),
+    method("sql", "transaction", "db.transaction(|tx| ...) -> Dynamic", "Runs the closure in a transaction, committing when it returns and rolling back when it throws. `tx` has `query` and `execute`."),
+    function("env", "get", "env::get(key: String, default?: Dynamic) -> Dynamic", "The value from `[env]`, then the process environment, then `default` or `()`."),
+    function("env", "has", "env::has(key: String) -> bool", "Whether `key` is in `[env]` or the process environment."),
+    function("http", "get", "http::get(url: String) -> Http", "Sends a GET request."),
+    function("http", "post", "http::post(url: String, data: Map) -> Http", "Sends a POST request with a json body."),
+    method("http", "json", "res.json() -> Map", "Parses the response body as json."),
+    method("http", "raw", "res.raw() -> Map", "The response as a map."),
+    method("http", "status", "res.status -> int", "The response status code."),
+    method("http", "body", "res.body -> String", "The response body."),
+    method("http", "length", "res.length -> int", "Length of the response body."),
+    method("http", "error", "res.error -> Map", "Error details when the request failed."),
+    function("json", "dump", "json::dump(object: Dynamic) -> String", "Serializes a value to a json string."),
+    function("json", "parse", "json::parse(json: String) -> Map", "Parses a json string."),
+    function("cmd", "run", "cmd::run(command: String) -> String", "Runs a command and returns its output."),
+    function("cmd", "start", "cmd::start(command: String)", "Starts a command in the background."),
+    function("cmd", "command_exists", "cmd::command_exists(command: String) -> bool", "Whether `command` is on the path."),
+    function("cmd", "pwd", "cmd::pwd() -> String", "The current working directory."),
+    function("tar", "extract", "tar::extract(path: String)", "Extracts a .tar.gz archive."),
+    function("tar", "compress", "tar::compress(files: Array, output: String)", "Creates a .tar.gz archive from `files`."),
+    function("exists", "file", "exists::file(path: String) -> bool", "Whether `path` exists."),
+    function("exists", "folder", "exists::folder(path: String) -> bool", "Whether `path` is a directory."),
+];
+
+pub fn module(name: &str) -> Option<&'static str> { MODULES.iter().find(|(module, _)| *module == name).map(|(_, doc)| *doc) }
+
+pub fn lookup(module: Option<&str>, name: &str) -> Vec<&'static Doc> { DOCS.iter().filter(|doc| doc.name == name && (module.is_none() || doc.module == module)).collect() }
@@ -4,6 +4,7 @@ mod database;
 mod globals;
 mod helpers;
 mod http;
+mod lsp;
 mod modules;
 mod routes;
 mod structs;
@@ -18,6 +19,7 @@ pub mod prelude {
 
 use crate::prelude::*;
 use clap::Subcommand;
+use std::path::PathBuf;
 use cli::verbose::{InfoLevel, Verbosity};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_subscriber::prelude::*;
@@ -29,18 +31,26 @@ pub struct Cli {
     #[arg(short, long, default_value = "config.toml")]
     pub config: String,
 
+    /// Merge config.<profile>.toml over the config, such as prod
+    #[arg(short = 'P', long)]
+    pub profile: Option<String>,
+
     #[arg(short, long)]
     /// Override config address
     pub address: Option<String>,
 
-    #[arg(short, long)]
-    /// Override cache directory
+    #[arg(short = 'C', long)]
+    /// Override cache directory, or "memory" to keep routes in memory only
     pub cache: Option<String>,
 
     /// Override config port
     #[arg(short, long)]
     pub port: Option<u16>,
 
+    /// Load environment variables from this file instead of a .env next to the config
+    #[arg(long)]
+    pub env_file: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Option<Commands>,
 
@@ -81,6 +91,93 @@ enum Cache {
     },
 }
 
+#[derive(Subcommand)]
+enum Routes {
+    /// Explain which route would handle a url
+    Match {
+        /// Url path, such as /name/5/bob
+        url: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum Config {
+    /// Print the effective config with secrets redacted
+    #[command(visible_alias = "print")]
+    Show,
+
+    /// Validate the config and report every problem
+    #[command(visible_alias = "validate")]
+    Check,
+}
+
+#[derive(Subcommand)]
+enum Db {
+    /// Apply every pending migration
+    #[command(visible_alias = "up")]
+    Migrate,
+
+    /// Revert the most recently applied migrations
+    #[command(visible_alias = "down")]
+    Rollback {
+        /// Number of migrations to revert
+        #[arg(short, long, default_value_t = 1)]
+        steps: usize,
+    },
+
+    /// List applied and pending migrations
+    Status,
+}
+
+#[derive(Subcommand)]
+enum Kv {
+    /// List the keys, optionally only those starting with a prefix
+    #[command(visible_alias = "ls")]
+    List {
+        /// Key prefix
+        prefix: Option<String>,
+    },
+
+    /// Print the value of a key as json
+    Get {
+        /// Key name
+        key: String,
+    },
+
+    /// Store a value, parsed as json when possible
+    Set {
+        /// Key name
+        key: String,
+
+        /// Value, such as 5, '{"name": "bob"}' or plain text
+        value: String,
+
+        /// Expire the key after this many seconds
+        #[arg(long)]
+        ttl: Option<u64>,
+    },
+
+    /// Remove a key
+    #[command(visible_alias = "rm")]
+    Del {
+        /// Key name
+        key: String,
+    },
+
+    /// Write every key as JSON Lines
+    Export {
+        /// File to write, defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Load keys from JSON Lines written by export
+    Import {
+        /// File to read, defaults to stdin
+        input: Option<PathBuf>,
+    },
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Cache management
@@ -88,33 +185,212 @@ enum Commands {
         #[command(subcommand)]
         command: Cache,
     },
+
+    /// Run a route in-process and print the response
+    Call {
+        /// Request method, such as GET or POST
+        method: String,
+
+        /// Url path with an optional query, such as /name/5/bob?page=2
+        url: String,
+
+        /// Request header as key=value, may be repeated
+        #[arg(short = 'H', long = "header")]
+        headers: Vec<String>,
+
+        /// Request body, or @path to read it from a file
+        #[arg(short, long)]
+        body: Option<String>,
+
+        /// Only print the response body
+        #[arg(short, long)]
+        raw: bool,
+    },
+
+    /// Validate worker files without touching the cache
+    #[command(visible_alias = "lint")]
+    Check {
+        /// Treat warnings as errors
+        #[arg(long)]
+        strict: bool,
+    },
+
+    /// Inspect the merged config
+    Config {
+        #[command(subcommand)]
+        command: Config,
+    },
+
+    /// Apply and roll back sqlite migrations
+    Db {
+        #[command(subcommand)]
+        command: Db,
+    },
+
+    /// Run the test blocks in the workers
+    Test {
+        /// Only run tests whose name contains this text
+        filter: Option<String>,
+
+        /// Write a JUnit XML report to this path
+        #[arg(long)]
+        junit: Option<PathBuf>,
+    },
+
+    /// Format worker files in the canonical style
+    Fmt {
+        /// Files to format, defaults to the configured workers
+        files: Vec<PathBuf>,
+
+        /// Fail if any file would be reformatted instead of writing it
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// Create a project in the current directory
+    Init {
+        /// Starter routes to generate
+        #[arg(short, long, value_enum, default_value = "site")]
+        template: cli::new::Starter,
+
+        /// Also write a tests.rt file
+        #[arg(long)]
+        tests: bool,
+    },
+
+    /// Inspect and edit a kv store
+    Kv {
+        /// Path of the store, as passed to kv::load
+        file: String,
+
+        #[command(subcommand)]
+        command: Kv,
+    },
+
+    /// Start a language server for worker files over stdio
+    Lsp,
+
+    /// Create a project in a new directory
+    New {
+        /// Directory to create the project in
+        dir: PathBuf,
+
+        /// Starter routes to generate
+        #[arg(short, long, value_enum, default_value = "site")]
+        template: cli::new::Starter,
+
+        /// Also write a tests.rt file
+        #[arg(long)]
+        tests: bool,
+    },
+
+    /// Evaluate scripts interactively with every route module loaded
+    Repl {
+        /// Url of the synthetic `request` object
+        #[arg(long, default_value = "/")]
+        url: String,
+    },
+
+    /// Print the routing table in match order
+    Routes {
+        #[command(subcommand)]
+        command: Option<Routes>,
+    },
 }
 
 fn main() {
     let cli = Cli::parse();
+
+    // scaffolding runs before a config exists
+    match &cli.command {
+        Some(Commands::Init { template, tests }) => return cli::new::create(PathBuf::from("."), *template, *tests),
+        Some(Commands::New { dir, template, tests }) => return cli::new::create(dir.to_owned(), *template, *tests),
+        _ => {}
+    }
+
     let config = globals::init(&cli);
 
-    let formatting_layer_config = BunyanFormattingLayer::new("server".into(), std::io::stdout)
-        .skip_fields(vec!["file", "line"].into_iter())
-        .expect("Unable to create logger");
+    // only commands that run scripts need working databases
+    let databases = matches!(cli.command, None | Some(Commands::Call { .. } | Commands::Test { .. } | Commands::Repl { .. } | Commands::Db { .. }));
 
-    tracing_subscriber::registry()
-        .with(cli.verbose.log_level_filter())
-        .with(JsonStorageLayer)
-        .with(formatting_layer_config)
-        .init();
+    // the language server and config commands work with invalid configs
+    if !matches!(cli.command, Some(Commands::Lsp | Commands::Config { .. })) {
+        config.ensure_valid(databases);
+    }
+
+    if databases {
+        database::pool::init(&config);
+    }
+
+    // stdout carries the protocol when running as a language server
+    if !matches!(cli.command, Some(Commands::Lsp)) {
+        // kv export writes its data to stdout, so logs such as a pickledb migration go to stderr
+        let to_stderr = matches!(cli.command, Some(Commands::Kv { .. }));
+        let writer = move || -> Box<dyn std::io::Write> {
+            match to_stderr {
+                true => Box::new(std::io::stderr()),
+                false => Box::new(std::io::stdout()),
+            }
+        };
+
+        let formatting_layer_config = BunyanFormattingLayer::new("server".into(), writer)
+            .skip_fields(vec!["file", "line"].into_iter())
+            .expect("Unable to create logger");
+
+        tracing_subscriber::registry()
+            .with(cli.verbose.log_level_filter())
+            .with(JsonStorageLayer)
+            .with(formatting_layer_config)
+            .init();
+    }
 
     match &cli.command {
         Some(Commands::Cache { command }) => match command {
-            Cache::List => cli::cache::list(config),
+            Cache::List => cli::cache::list(),
             Cache::Clean => cli::cache::clean(config),
             Cache::Build => cli::cache::build(config),
             Cache::Debug => {}
             Cache::View { route } => {}
             Cache::Remove { route } => {}
         },
-        None => http::start(config).unwrap_or_else(|err| {
-            crashln!("Failed to start server!\n{:?}", err);
+        Some(Commands::Call { method, url, headers, body, raw }) => cli::call::call(config, method.to_owned(), url.to_owned(), headers.to_owned(), body.to_owned(), *raw),
+        Some(Commands::Check { strict }) => cli::check::check(config, *strict),
+        Some(Commands::Config { command }) => match command {
+            Config::Show => cli::config::show(config),
+            Config::Check => cli::config::check(config),
+        },
+        Some(Commands::Db { command }) => match command {
+            Db::Migrate => cli::db::migrate(config),
+            Db::Rollback { steps } => cli::db::rollback(config, *steps),
+            Db::Status => cli::db::status(config),
+        },
+        Some(Commands::Kv { file, command }) => match command {
+            Kv::List { prefix } => cli::kv::list(file, prefix.to_owned()),
+            Kv::Get { key } => cli::kv::get(file, key),
+            Kv::Set { key, value, ttl } => cli::kv::set(file, key.to_owned(), value, *ttl),
+            Kv::Del { key } => cli::kv::del(file, key),
+            Kv::Export { output } => cli::kv::export(file, output.to_owned()),
+            Kv::Import { input } => cli::kv::import(file, input.to_owned()),
+        },
+        Some(Commands::Lsp) => lsp::start(config).unwrap_or_else(|err| {
+            crashln!("Language server failed!\n{:?}", err);
         }),
+        Some(Commands::Fmt { files, check }) => cli::fmt::format(config, files.to_owned(), *check),
+        Some(Commands::Init { .. } | Commands::New { .. }) => {}
+        Some(Commands::Repl { url }) => cli::repl::repl(config, url.to_owned()),
+        Some(Commands::Routes { command }) => match command {
+            Some(Routes::Match { url }) => cli::routes::explain(config, url.to_owned()),
+            None => cli::routes::table(config),
+        },
+        Some(Commands::Test { filter, junit }) => cli::test::test(config, filter.to_owned(), junit.to_owned()),
+        None => {
+            if let Err(err) = database::migrate::on_start(&config) {
+                crashln!("Failed to migrate the database!\n{err}");
+            }
+
+            http::start(config).unwrap_or_else(|err| {
+                crashln!("Failed to start server!\n{:?}", err);
+            })
+        }
     }
 }
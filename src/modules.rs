@@ -1,4 +1,5 @@
 pub mod compress;
+pub mod env;
 pub mod file;
 pub mod http;
 pub mod parse;
@@ -7,6 +8,7 @@ pub mod shell;
 
 pub mod prelude {
     pub use super::compress::*;
+    pub use super::env::env_vars;
     pub use super::file::*;
     pub use super::http::*;
     pub use super::parse::*;
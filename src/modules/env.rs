@@ -0,0 +1,31 @@
+use rhai::{plugin::*, serde::to_dynamic};
+use std::{collections::BTreeMap, sync::RwLock};
+use toml::Value;
+
+static VALUES: RwLock<BTreeMap<String, Value>> = RwLock::new(BTreeMap::new());
+
+/// Makes the `[env]` table of the config visible to scripts.
+pub fn load(values: BTreeMap<String, Value>) { *VALUES.write().unwrap() = values; }
+
+/// The `[env]` table as a script map, pushed into every scope as `env`.
+pub fn table() -> Dynamic { to_dynamic(&*VALUES.read().unwrap()).unwrap_or_default() }
+
+/// Looks in `[env]` first and then the process environment.
+fn lookup(key: &str) -> Option<Dynamic> {
+    if let Some(value) = VALUES.read().unwrap().get(key) {
+        return to_dynamic(value).ok();
+    }
+
+    std::env::var(key).ok().map(Dynamic::from)
+}
+
+#[export_module]
+pub mod env_vars {
+    #[rhai_fn(name = "get")]
+    pub fn get(key: &str) -> Dynamic { super::lookup(key).unwrap_or(Dynamic::UNIT) }
+
+    #[rhai_fn(name = "get")]
+    pub fn get_or(key: &str, default: Dynamic) -> Dynamic { super::lookup(key).unwrap_or(default) }
+
+    pub fn has(key: &str) -> bool { super::lookup(key).is_some() }
+}
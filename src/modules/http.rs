@@ -1,4 +1,4 @@
-use crate::structs::modules::*;
+use crate::{database::mongo::plain, structs::modules::*};
 use macros_rs::fmt::{str, string};
 use reqwest::blocking::Client as ReqwestClient;
 use rhai::{plugin::*, FnNamespace, Map};
@@ -59,7 +59,7 @@ pub mod http {
     pub fn post(url: String, data: Map) -> Http {
         let client = ReqwestClient::new();
 
-        let data = match serde_json::to_string(&data) {
+        let data = match serde_json::to_string(&plain(Dynamic::from_map(data))) {
             Ok(result) => result,
             Err(err) => err.to_string(),
         };
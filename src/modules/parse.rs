@@ -1,9 +1,10 @@
+use crate::database::mongo::plain;
 use rhai::{plugin::*, Map};
 
 #[export_module]
 pub mod json {
     pub fn dump<'s>(object: Dynamic) -> String {
-        match serde_json::to_string(&object) {
+        match serde_json::to_string(&plain(object)) {
             Ok(result) => result,
             Err(err) => err.to_string(),
         }
@@ -1,4 +1,4 @@
-use crate::helpers::convert_status;
+use crate::{database::mongo::plain, helpers::convert_status};
 use actix_web::http::{header::ContentType, StatusCode};
 use rhai::plugin::*;
 
@@ -9,7 +9,7 @@ pub mod default {
     pub fn html(string: String) -> (String, ContentType, StatusCode) { (string, ContentType::html(), StatusCode::OK) }
 
     pub fn json(object: Dynamic) -> (String, ContentType, StatusCode) {
-        match serde_json::to_string(&object) {
+        match serde_json::to_string(&plain(object)) {
             Ok(result) => (result, ContentType::json(), StatusCode::OK),
             Err(err) => (err.to_string(), ContentType::plaintext(), StatusCode::INTERNAL_SERVER_ERROR),
         }
@@ -23,7 +23,7 @@ pub mod status {
     pub fn html(string: String, status: i64) -> (String, ContentType, StatusCode) { (string, ContentType::html(), convert_status(status)) }
 
     pub fn json(object: Dynamic, status: i64) -> (String, ContentType, StatusCode) {
-        match serde_json::to_string(&object) {
+        match serde_json::to_string(&plain(object)) {
             Ok(result) => (result, ContentType::json(), convert_status(status)),
             Err(err) => (err.to_string(), ContentType::plaintext(), StatusCode::INTERNAL_SERVER_ERROR),
         }
@@ -0,0 +1,232 @@
+use super::Route;
+use crate::structs::config::CacheFormat;
+
+use anyhow::{anyhow, Error};
+use global_placeholders::global;
+use macros_rs::{fmt::string, obj::lazy_lock};
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+/// Bumped whenever the on-disk layout of `Route` or the manifest changes.
+pub const CACHE_VERSION: u32 = 1;
+
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+static MEMORY: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub build: String,
+    pub format: CacheFormat,
+    pub routes: BTreeMap<String, Entry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Entry {
+    pub route: String,
+    pub fn_name: String,
+}
+
+pub type Checksums = BTreeMap<String, String>;
+
+#[derive(Default)]
+struct State {
+    manifest: Manifest,
+    checksums: Checksums,
+    dirty: bool,
+}
+
+lazy_lock! {
+    static MANIFEST: Mutex<State> = Mutex::new(Default::default());
+}
+
+pub fn is_memory() -> bool { MEMORY.load(Ordering::Relaxed) }
+
+pub fn use_memory() { MEMORY.store(true, Ordering::Relaxed) }
+
+/// Keeps serving from `ROUTES_INDEX` when the cache directory stops being writable.
+pub fn downgrade(err: impl std::fmt::Display) {
+    if !MEMORY.swap(true, Ordering::Relaxed) {
+        log::error!(err = err.to_string(), "Cannot write route cache, falling back to memory");
+    }
+}
+
+pub fn build_id() -> String { format!("{}-{}", env!("CARGO_PKG_VERSION"), env!("GIT_HASH").trim()) }
+
+pub fn checksum(bytes: &[u8]) -> String {
+    let mut md5 = Md5::new();
+    md5.update(bytes);
+    const_hex::encode(md5.finalize())
+}
+
+fn index_path() -> PathBuf { PathBuf::from(global!("dirs.cache.index")) }
+
+fn hash_path() -> PathBuf { PathBuf::from(global!("dirs.cache.hash")) }
+
+fn read_manifest() -> Result<(Manifest, Checksums), Error> {
+    let manifest = toml::from_str(&fs::read_to_string(index_path())?)?;
+    let checksums = toml::from_str(&fs::read_to_string(hash_path())?)?;
+
+    Ok((manifest, checksums))
+}
+
+fn purge() -> io::Result<()> {
+    let base = PathBuf::from(global!("base.cache"));
+
+    for dir in [base.join("cache"), PathBuf::from(global!("base.handler"))] {
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+    }
+
+    for file in [index_path(), hash_path()] {
+        if file.exists() {
+            fs::remove_file(file)?;
+        }
+    }
+
+    fs::create_dir_all(base.join("cache"))
+}
+
+/// Loads the cache manifest, wiping the cache when it was written by another
+/// build, uses a different format or can no longer be read.
+pub fn load(format: CacheFormat) {
+    let expected = Manifest {
+        format,
+        version: CACHE_VERSION,
+        build: build_id(),
+        routes: BTreeMap::new(),
+    };
+
+    let reason = match read_manifest() {
+        Ok((manifest, checksums)) => {
+            if manifest.version != expected.version {
+                Some(format!("cache version {} is incompatible", manifest.version))
+            } else if manifest.build != expected.build {
+                Some(format!("cache was built by {}", manifest.build))
+            } else if manifest.format != expected.format {
+                Some(string!("cache format changed"))
+            } else {
+                *MANIFEST.blocking_lock() = State { manifest, checksums, dirty: false };
+                None
+            }
+        }
+        Err(_) if !index_path().exists() => None,
+        Err(err) => Some(format!("manifest is unreadable, {err}")),
+    };
+
+    if let Some(reason) = reason {
+        log::warn!(reason, "rebuilding route cache");
+
+        if let Err(err) = purge() {
+            log::error!(err = string!(err), "Cannot purge route cache");
+        }
+    }
+
+    if !index_path().exists() {
+        *MANIFEST.blocking_lock() = State {
+            manifest: expected,
+            checksums: Checksums::new(),
+            dirty: true,
+        };
+    }
+}
+
+pub async fn manifest() -> Manifest { MANIFEST.lock().await.manifest.to_owned() }
+
+pub async fn format() -> CacheFormat { MANIFEST.lock().await.manifest.format }
+
+pub fn encode(route: &Route, format: CacheFormat) -> Result<Vec<u8>, Error> {
+    match format {
+        CacheFormat::Ron => Ok(ron::ser::to_string(route)?.into_bytes()),
+        CacheFormat::Binary => Ok(bincode::serialize(route)?),
+    }
+}
+
+pub fn decode(bytes: &[u8], format: CacheFormat) -> Result<Route, Error> {
+    match format {
+        CacheFormat::Ron => Ok(ron::de::from_bytes(bytes)?),
+        CacheFormat::Binary => Ok(bincode::deserialize(bytes)?),
+    }
+}
+
+/// Checks a cached route file against the checksum recorded when it was written.
+pub async fn verify(path: &Path, bytes: &[u8]) -> Result<(), Error> {
+    let key = path.to_string_lossy();
+    let state = MANIFEST.lock().await;
+
+    match state.checksums.get(key.as_ref()) {
+        Some(expected) if *expected == checksum(bytes) => Ok(()),
+        Some(_) => Err(anyhow!("checksum mismatch for {key}")),
+        None => Err(anyhow!("{key} is not in the cache manifest")),
+    }
+}
+
+pub async fn record(route: &Route, bytes: &[u8]) {
+    let key = route.cache.to_string_lossy().to_string();
+    let state = &mut *MANIFEST.lock().await;
+
+    state.dirty = true;
+    state.checksums.insert(key.to_owned(), checksum(bytes));
+    state.manifest.routes.insert(
+        key,
+        Entry {
+            route: route.route.to_string(),
+            fn_name: route.fn_name.to_string(),
+        },
+    );
+}
+
+/// Writes to a sibling temp file first so readers never observe a partial file.
+pub async fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let id = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(format!(".{}.{id}.tmp", process::id()));
+
+    tokio::fs::write(&temp, bytes).await?;
+
+    match tokio::fs::rename(&temp, path).await {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            let _ = tokio::fs::remove_file(&temp).await;
+            Err(err)
+        }
+    }
+}
+
+pub fn is_internal(path: &Path) -> bool {
+    path == index_path() || path == hash_path() || path == Path::new(&global!("dirs.history")) || path.extension().is_some_and(|ext| ext == "tmp")
+}
+
+/// Drops entries for routes that were removed and writes the manifest to disk.
+pub async fn persist(valid: impl Iterator<Item = PathBuf>) -> Result<(), Error> {
+    if is_memory() {
+        return Ok(());
+    }
+
+    let valid: Vec<String> = valid.map(|path| path.to_string_lossy().to_string()).collect();
+    let state = &mut *MANIFEST.lock().await;
+    let count = state.checksums.len();
+
+    state.manifest.routes.retain(|key, _| valid.contains(key));
+    state.checksums.retain(|key, _| valid.contains(key));
+
+    if !state.dirty && count == state.checksums.len() {
+        return Ok(());
+    }
+
+    write_atomic(&hash_path(), toml::to_string(&state.checksums)?.as_bytes()).await?;
+    write_atomic(&index_path(), toml::to_string(&state.manifest)?.as_bytes()).await?;
+    state.dirty = false;
+
+    Ok(())
+}
@@ -0,0 +1,309 @@
+use super::parse::{RouteParser, Rule};
+use pest::{error::Error, iterators::Pair, Parser};
+
+pub const INDENT: &str = "   ";
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Code,
+    Str(char),
+    LineComment,
+    BlockComment,
+}
+
+/// Tracks nesting and string/comment state across the lines of a script.
+struct Scanner {
+    /// Line number of every unclosed bracket, in opening order.
+    open: Vec<usize>,
+    line: usize,
+    state: State,
+    comments: bool,
+}
+
+impl Scanner {
+    fn new() -> Self {
+        Scanner {
+            open: vec![],
+            line: 0,
+            state: State::Code,
+            comments: false,
+        }
+    }
+
+    /// Indent level once `closing` brackets are closed, brackets opened on the same line count once.
+    fn level(&self, closing: usize) -> usize {
+        let mut open = self.open[..self.open.len().saturating_sub(closing)].to_vec();
+        open.dedup();
+        open.len()
+    }
+
+    /// Consumes one line, returning whether it started inside a string or block comment.
+    fn line(&mut self, line: &str) -> bool {
+        let verbatim = matches!(self.state, State::Str(_) | State::BlockComment);
+        let mut chars = line.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match self.state {
+                State::Code => match c {
+                    '"' | '`' | '\'' => self.state = State::Str(c),
+                    '/' if chars.peek() == Some(&'/') => {
+                        self.comments = true;
+                        self.state = State::LineComment;
+                    }
+                    '/' if chars.peek() == Some(&'*') => {
+                        chars.next();
+                        self.comments = true;
+                        self.state = State::BlockComment;
+                    }
+                    '{' | '(' | '[' => self.open.push(self.line),
+                    '}' | ')' | ']' => {
+                        self.open.pop();
+                    }
+                    _ => {}
+                },
+                State::Str(quote) => match c {
+                    '\\' => {
+                        chars.next();
+                    }
+                    c if c == quote => self.state = State::Code,
+                    _ => {}
+                },
+                State::BlockComment if c == '*' && chars.peek() == Some(&'/') => {
+                    chars.next();
+                    self.state = State::Code;
+                }
+                _ => {}
+            }
+        }
+
+        self.line += 1;
+
+        match self.state {
+            State::LineComment | State::Str('"') | State::Str('\'') => self.state = State::Code,
+            _ => {}
+        }
+
+        verbatim
+    }
+}
+
+fn has_comment(text: &str) -> bool {
+    let mut scanner = Scanner::new();
+    text.lines().for_each(|line| {
+        scanner.line(line);
+    });
+    scanner.comments
+}
+
+fn find_block(pair: Pair<Rule>) -> Option<Pair<Rule>> {
+    match pair.as_rule() {
+        Rule::block => Some(pair),
+        _ => pair.into_inner().filter(|inner| matches!(inner.as_rule(), Rule::block | Rule::function_def)).find_map(find_block),
+    }
+}
+
+/// Re-indents a block body, keeping multi-line strings and comments untouched.
+fn body(inner: &str, depth: usize) -> Vec<String> {
+    let mut lines: Vec<&str> = inner.lines().collect();
+
+    while lines.first().is_some_and(|line| line.trim().is_empty()) {
+        lines.remove(0);
+    }
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+
+    let mut scanner = Scanner::new();
+    let mut formatted: Vec<String> = Vec::new();
+
+    for line in lines {
+        let trimmed = line.trim();
+        let closing = trimmed.chars().take_while(|c| matches!(c, '}' | ')' | ']')).count();
+        let level = scanner.level(closing);
+
+        if scanner.line(line) {
+            formatted.push(line.trim_end().to_string());
+            continue;
+        }
+
+        if trimmed.is_empty() {
+            if formatted.last().is_some_and(|last| !last.is_empty()) {
+                formatted.push(String::new());
+            }
+            continue;
+        }
+
+        let continuation = usize::from(trimmed.starts_with('.') && !trimmed.starts_with(".."));
+        let level = level + continuation + depth;
+
+        formatted.push(format!("{}{trimmed}", INDENT.repeat(level)));
+    }
+
+    formatted
+}
+
+fn block(pair: Pair<Rule>) -> String {
+    let text = pair.as_str();
+    let inner = &text[1..text.len() - 1];
+    let lines = body(inner, 1);
+
+    match lines.is_empty() {
+        true => "{}".into(),
+        false => format!("{{\n{}\n}}", lines.join("\n")),
+    }
+}
+
+fn attribute(pair: Pair<Rule>) -> String {
+    let name = match pair.as_rule() {
+        Rule::subscribe_attr => "subscribe",
+        _ => "route",
+    };
+
+    let mut route = String::new();
+    let mut cfg = Vec::new();
+
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::string_literal => route = inner.as_str().to_string(),
+            Rule::cfg_block => {
+                for entry in inner.into_inner().flat_map(|entries| entries.into_inner()) {
+                    let mut parts = entry.into_inner();
+                    if let (Some(key), Some(value)) = (parts.next(), parts.next()) {
+                        cfg.push(format!("{} = {}", key.as_str(), value.as_str()));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    match cfg.is_empty() {
+        true => format!("#[{name}({route})]"),
+        false => format!("#[{name}({route}), cfg({})]", cfg.join(", ")),
+    }
+}
+
+fn function(pair: Pair<Rule>) -> String {
+    let text = pair.as_str();
+    let mut name = String::new();
+    let mut params: Option<Vec<&str>> = text.split('{').next().filter(|head| head.contains('(')).map(|_| vec![]);
+    let mut body = String::new();
+
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::route_name => name = inner.as_str().to_string(),
+            Rule::parameters => params = Some(inner.into_inner().map(|param| param.as_str()).collect()),
+            Rule::block => body = block(inner),
+            _ => {}
+        }
+    }
+
+    match params {
+        Some(params) => format!("{name}({}) {body}", params.join(", ")),
+        None => format!("{name} {body}"),
+    }
+}
+
+fn item(pair: Pair<Rule>) -> String {
+    let text = pair.as_str();
+    let start = pair.as_span().start();
+
+    // leave headers with comments as they were written
+    if let Some(inner) = find_block(pair.clone()) {
+        let header = &text[..inner.as_span().start() - start];
+        if has_comment(header) {
+            return format!("{} {}", header.trim(), block(inner));
+        }
+    }
+
+    match pair.as_rule() {
+        Rule::route_definition | Rule::subscription => pair
+            .into_inner()
+            .map(|inner| match inner.as_rule() {
+                Rule::route_attr | Rule::subscribe_attr => attribute(inner),
+                _ => function(inner),
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Rule::function_def => function(pair),
+        Rule::test_block => {
+            let mut inner = pair.into_inner();
+            let name = inner.next().map(|name| name.as_str().to_string()).unwrap_or_default();
+            format!("test {name} {}", inner.next().map(block).unwrap_or_default())
+        }
+        Rule::not_found => format!("404 {}", pair.into_inner().next().map(block).unwrap_or_default()),
+        Rule::wildcard => format!("* {}", pair.into_inner().next().map(block).unwrap_or_default()),
+        _ => text.to_string(),
+    }
+}
+
+/// Comments found between two items, the first line may trail the previous item.
+fn gap(text: &str) -> (Option<String>, Vec<String>) {
+    let (first, rest) = match text.split_once('\n') {
+        Some((first, rest)) => (first, rest),
+        None => (text, ""),
+    };
+
+    let trailing = Some(first.trim()).filter(|first| !first.is_empty()).map(str::to_string);
+    let mut scanner = Scanner::new();
+    let mut lines: Vec<String> = Vec::new();
+
+    for line in rest.lines() {
+        match scanner.line(line) {
+            true => lines.push(line.trim_end().to_string()),
+            false if line.trim().is_empty() => {
+                if lines.last().is_some_and(|last| !last.is_empty()) {
+                    lines.push(String::new());
+                }
+            }
+            false => lines.push(line.trim().to_string()),
+        }
+    }
+
+    (trailing, lines)
+}
+
+/// Formats a worker file into the canonical style, comments are preserved.
+pub fn format(input: &str) -> Result<String, Box<Error<Rule>>> {
+    let mut output = String::new();
+    let mut cursor = 0;
+
+    let items: Vec<Pair<Rule>> = RouteParser::parse(Rule::grammar, input)?.flat_map(|pair| pair.into_inner()).filter(|pair| pair.as_rule() != Rule::EOI).collect();
+
+    for pair in items {
+        let span = pair.as_span();
+        let between = &input[cursor..span.start()];
+        let (trailing, comments) = match cursor {
+            0 => gap(&format!("\n{between}")),
+            _ => gap(between),
+        };
+
+        if let Some(trailing) = trailing {
+            output += &format!(" {trailing}");
+        }
+        if !output.is_empty() {
+            output += "\n\n";
+        }
+
+        for line in comments.iter().skip_while(|line| line.is_empty()) {
+            output += &format!("{line}\n");
+        }
+
+        output += &item(pair);
+        cursor = span.end();
+    }
+
+    let (trailing, comments) = gap(&input[cursor..]);
+
+    if let Some(trailing) = trailing {
+        output += &format!(" {trailing}");
+    }
+
+    let comments: Vec<&String> = comments.iter().skip_while(|line| line.is_empty()).collect();
+    if !comments.is_empty() {
+        output += "\n\n";
+        output += &comments.iter().map(|line| line.as_str()).collect::<Vec<_>>().join("\n");
+    }
+
+    Ok(format!("{}\n", output.trim_end()))
+}
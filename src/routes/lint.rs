@@ -0,0 +1,221 @@
+use super::{parse, RtKind, Route};
+use pest::error::LineColLocation;
+use rhai::Engine;
+use std::{collections::HashMap, path::PathBuf};
+
+pub const KNOWN_CFG: [&str; 1] = ["wildcard"];
+
+const TRUTHY: [&str; 4] = ["true", "1", "yes", "on"];
+const FALSY: [&str; 4] = ["false", "0", "no", "off"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Debug)]
+pub struct Problem {
+    pub severity: Severity,
+    pub file: PathBuf,
+    pub line: usize,
+    pub message: String,
+}
+
+/// A route together with the worker file it was defined in.
+#[derive(Clone, Debug)]
+pub struct Source {
+    pub file: PathBuf,
+    pub kind: RtKind,
+    pub route: Route,
+}
+
+impl Source {
+    /// 1-based line of the route definition.
+    pub fn line(&self) -> usize { self.route.start_pos + 1 }
+
+    pub fn location(&self) -> String { format!("{}:{}", self.file.display(), self.line()) }
+}
+
+impl Problem {
+    fn error(source: &Source, message: String) -> Self {
+        Problem {
+            severity: Severity::Error,
+            file: source.file.to_owned(),
+            line: source.line(),
+            message,
+        }
+    }
+
+    fn warning(source: &Source, message: String) -> Self {
+        Problem {
+            severity: Severity::Warning,
+            file: source.file.to_owned(),
+            line: source.line(),
+            message,
+        }
+    }
+}
+
+pub fn placeholders(route: &str) -> Vec<String> {
+    route
+        .split('{')
+        .skip(1)
+        .filter_map(|part| part.split_once('}'))
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+/// Parses a single worker file, normalising each route the same way the cache does.
+pub fn parse_file(file: PathBuf, input: &str) -> Result<Vec<Source>, Problem> {
+    let routes = parse::parse(input).map_err(|err| {
+        let line = match err.line_col {
+            LineColLocation::Pos((line, _)) => line,
+            LineColLocation::Span((line, _), _) => line,
+        };
+
+        Problem {
+            line,
+            file: file.to_owned(),
+            severity: Severity::Error,
+            message: format!("syntax error, {}", err.variant.message()),
+        }
+    })?;
+
+    Ok(routes
+        .into_iter()
+        .map(|(kind, mut route)| {
+            route.cache(&kind);
+            Source { file: file.to_owned(), kind, route }
+        })
+        .collect())
+}
+
+fn duplicates(sources: &[Source], problems: &mut Vec<Problem>) {
+    let mut routes: HashMap<&str, &Source> = HashMap::new();
+    let mut functions: HashMap<&str, &Source> = HashMap::new();
+
+    for source in sources {
+        match routes.get(source.route.route.as_str()) {
+            Some(first) => problems.push(Problem::error(source, format!("duplicate route '{}' (first defined at {})", source.route.route, first.location()))),
+            None => {
+                routes.insert(&source.route.route, source);
+            }
+        }
+
+        if source.kind != RtKind::Normal {
+            continue;
+        }
+
+        match functions.get(source.route.fn_name.as_str()) {
+            Some(first) => problems.push(Problem::warning(source, format!("duplicate function name '{}' (first defined at {})", source.route.fn_name, first.location()))),
+            None => {
+                functions.insert(&source.route.fn_name, source);
+            }
+        }
+    }
+}
+
+fn covers(general: &str, specific: &str) -> bool {
+    let general: Vec<&str> = general.split('/').collect();
+    let specific: Vec<&str> = specific.split('/').collect();
+
+    general.len() == specific.len()
+        && general.iter().zip(specific.iter()).all(|(g, s)| {
+            let bare = g.starts_with('{') && g.ends_with('}') && g.matches('{').count() == 1;
+            g == s || bare
+        })
+}
+
+fn shadowing(sources: &[Source], problems: &mut Vec<Problem>) {
+    let normal: Vec<&Source> = sources.iter().filter(|s| s.kind == RtKind::Normal).collect();
+
+    for (i, a) in normal.iter().enumerate() {
+        let wildcard = a.route.cfg.as_ref().and_then(|cfg| cfg.get("wildcard")).is_some_and(|v| TRUTHY.contains(&v.to_lowercase().as_str()));
+
+        for (j, b) in normal.iter().enumerate() {
+            if i == j || a.route.route == b.route.route {
+                continue;
+            }
+
+            if wildcard && b.route.route.starts_with(&format!("{}/", a.route.route)) {
+                problems.push(Problem::warning(b, format!("route '{}' is shadowed by wildcard route '{}' at {}", b.route.route, a.route.route, a.location())));
+                continue;
+            }
+
+            // literal routes always win through the exact lookup
+            if placeholders(&b.route.route).is_empty() || !covers(&a.route.route, &b.route.route) {
+                continue;
+            }
+
+            let mutual = covers(&b.route.route, &a.route.route);
+            if !mutual || i < j {
+                problems.push(Problem::warning(b, format!("route '{}' may be shadowed by '{}' at {}", b.route.route, a.route.route, a.location())));
+            }
+        }
+    }
+}
+
+fn arguments(source: &Source, problems: &mut Vec<Problem>) {
+    let expected = placeholders(&source.route.route);
+    let args: Vec<String> = source.route.args.to_owned().unwrap_or_default().iter().map(|arg| arg.to_string()).collect();
+
+    for name in expected.iter().filter(|name| !args.contains(name)) {
+        problems.push(Problem::error(source, format!("placeholder '{{{name}}}' in '{}' has no matching parameter", source.route.route)));
+    }
+
+    for name in args.iter().filter(|name| !expected.contains(name)) {
+        problems.push(Problem::error(source, format!("parameter '{name}' of '{}' is not a placeholder in '{}'", source.route.fn_name, source.route.route)));
+    }
+
+    let ordered: Vec<&String> = expected.iter().filter(|name| args.contains(name)).collect();
+    let given: Vec<&String> = args.iter().filter(|name| expected.contains(name)).collect();
+
+    if ordered != given {
+        problems.push(Problem::warning(source, format!("parameters of '{}' are not in placeholder order ({})", source.route.fn_name, expected.join(", "))));
+    }
+}
+
+fn config(source: &Source, problems: &mut Vec<Problem>) {
+    for (key, value) in source.route.cfg.iter().flatten() {
+        if !KNOWN_CFG.contains(&key.as_str()) {
+            problems.push(Problem::error(source, format!("unknown cfg key '{key}'")));
+        } else if key == "wildcard" && !TRUTHY.contains(&value.to_lowercase().as_str()) && !FALSY.contains(&value.to_lowercase().as_str()) {
+            problems.push(Problem::error(source, format!("cfg 'wildcard' expects a boolean, found '{value}'")));
+        }
+    }
+}
+
+fn compile(engine: &Engine, source: &Source, problems: &mut Vec<Problem>) {
+    if let Err(err) = engine.compile(source.route.construct_fn()) {
+        let line = match err.1.line() {
+            Some(line) => source.route.start_pos + 1 + line,
+            None => source.line(),
+        };
+
+        problems.push(Problem {
+            line,
+            severity: Severity::Error,
+            file: source.file.to_owned(),
+            message: format!("'{}' does not compile, {}", source.route.fn_name, err.0),
+        });
+    }
+}
+
+/// Runs every check over the parsed routes of all workers.
+pub fn lint(sources: &[Source]) -> Vec<Problem> {
+    let engine = Engine::new();
+    let mut problems = Vec::new();
+
+    duplicates(sources, &mut problems);
+    shadowing(sources, &mut problems);
+
+    for source in sources {
+        arguments(source, &mut problems);
+        config(source, &mut problems);
+        compile(&engine, source, &mut problems);
+    }
+
+    problems.sort_by(|a, b| (&a.file, a.line, a.severity).cmp(&(&b.file, b.line, b.severity)));
+    problems
+}
@@ -0,0 +1,182 @@
+use super::{lint::placeholders, Route, RtData, ROUTES_INDEX};
+use std::{cmp::Reverse, fmt};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Reason {
+    Wildcard,
+    Exact,
+    Pattern,
+    NotFound,
+    Fallback,
+}
+
+pub struct Resolved {
+    pub route: Route,
+    pub args: Vec<String>,
+    pub reason: Reason,
+    /// Other pattern routes that matched the url but lost on precedence.
+    pub shadowed: Vec<Route>,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            Reason::Wildcard => "prefix matched a route with cfg(wildcard = true)",
+            Reason::Exact => "exact match",
+            Reason::Pattern => "placeholder pattern match",
+            Reason::NotFound => "no route matched, handled by the 404 block",
+            Reason::Fallback => "no route matched, handled by the * block",
+        };
+        write!(f, "{reason}")
+    }
+}
+
+pub fn parse_bool(s: &str) -> bool { matches!(s.trim().to_lowercase().as_str(), "true" | "1" | "yes" | "on") }
+
+pub fn parse_slash(s: &str) -> String {
+    let parts: Vec<&str> = s.splitn(3, '/').collect();
+    if parts.len() > 1 {
+        format!("{}/{}", parts[0], parts[1])
+    } else {
+        s.to_string()
+    }
+}
+
+pub fn is_wildcard(route: &Route) -> bool { route.cfg.as_ref().and_then(|cfg| cfg.get("wildcard")).is_some_and(|val| parse_bool(val)) }
+
+/// Sort key for pattern routes, more literal characters and fewer placeholders win.
+pub fn precedence(route: &str) -> (Reverse<usize>, usize) {
+    let placeholders = placeholders(route);
+    let literal = route.len() - placeholders.iter().map(|name| name.len() + 2).sum::<usize>();
+
+    (Reverse(literal), placeholders.len())
+}
+
+pub fn match_route(route_template: &str, placeholders: &[RtData], url: &str) -> Option<Vec<String>> {
+    let route_segments: Vec<&str> = route_template.split('/').collect();
+    let url_segments: Vec<&str> = url.split('/').collect();
+
+    if route_segments.len() != url_segments.len() {
+        return None;
+    }
+
+    let mut matched = Vec::new();
+
+    for (route_segment, url_segment) in route_segments.into_iter().zip(url_segments) {
+        matched.append(&mut match_segment(route_segment, url_segment, placeholders)?);
+    }
+
+    Some(matched)
+}
+
+fn match_segment(route_segment: &str, url_segment: &str, placeholders: &[RtData]) -> Option<Vec<String>> {
+    let mut result = Vec::new();
+    let mut route_parts = route_segment.split('{');
+    let mut url_chars = url_segment.chars().peekable();
+
+    if let Some(prefix) = route_parts.next() {
+        if !url_segment.starts_with(prefix) {
+            return None;
+        }
+        for _ in 0..prefix.chars().count() {
+            url_chars.next();
+        }
+    }
+
+    for part in route_parts {
+        let (placeholder, suffix) = part.split_once('}')?;
+        if !placeholders.contains(&RtData::from(placeholder)) {
+            return None;
+        }
+
+        let mut value = String::new();
+        while let Some(&c) = url_chars.peek() {
+            if suffix.starts_with(c) {
+                break;
+            }
+            value.push(url_chars.next().unwrap());
+        }
+        result.push(value);
+
+        for expected_char in suffix.chars() {
+            if url_chars.next() != Some(expected_char) {
+                return None;
+            }
+        }
+    }
+
+    if url_chars.next().is_some() {
+        None
+    } else {
+        Some(result)
+    }
+}
+
+/// Every pattern route matching `url`, best match first.
+pub async fn candidates(url: &str) -> Vec<(Route, Vec<String>)> {
+    let index = ROUTES_INDEX.lock().await;
+    let mut matched: Vec<(Route, Vec<String>)> = index
+        .iter()
+        .filter_map(|entry| {
+            let route = &entry.value().inner;
+            if route.is_handler() {
+                return None;
+            }
+
+            let placeholders = route.args.to_owned().unwrap_or_default();
+            match_route(&route.route, &placeholders, url).map(|values| (route.to_owned(), values))
+        })
+        .collect();
+
+    matched.sort_by_key(|(route, _)| (precedence(&route.route), route.start_pos));
+    matched
+}
+
+/// Picks the route that serves `path`, in the same order the server uses.
+pub async fn resolve(path: &str) -> Option<Resolved> {
+    let prefix = parse_slash(path);
+
+    if let Ok(route) = Route::get(prefix.to_owned()).await {
+        if is_wildcard(&route) && prefix == route.route {
+            return Some(Resolved {
+                route,
+                args: vec![],
+                reason: Reason::Wildcard,
+                shadowed: vec![],
+            });
+        }
+    }
+
+    if let Ok(route) = Route::get(path.to_owned()).await {
+        return Some(Resolved {
+            route,
+            args: vec![],
+            reason: Reason::Exact,
+            shadowed: vec![],
+        });
+    }
+
+    let mut matched = candidates(path).await.into_iter();
+
+    if let Some((route, args)) = matched.next() {
+        return Some(Resolved {
+            route,
+            args,
+            reason: Reason::Pattern,
+            shadowed: matched.map(|(route, _)| route).collect(),
+        });
+    }
+
+    for (handler, reason) in [("__handler_not_found", Reason::NotFound), ("__handler_wildcard", Reason::Fallback)] {
+        if let Ok(route) = Route::get(handler.to_string()).await {
+            return Some(Resolved {
+                route,
+                reason,
+                args: vec![],
+                shadowed: vec![],
+            });
+        }
+    }
+
+    None
+}
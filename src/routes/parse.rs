@@ -3,12 +3,31 @@ use pest::iterators::Pair;
 use pest::{error::Error, Parser};
 use pest_derive::Parser;
 use std::collections::HashMap;
-use std::future::Future;
-use std::pin::Pin;
 
 #[derive(Parser)]
 #[grammar = "routes/grammar.peg"]
-struct RouteParser;
+pub(super) struct RouteParser;
+
+/// An in-file `test "name" { ... }` block.
+#[derive(Clone, Debug)]
+pub struct Case {
+    pub name: String,
+    pub body: String,
+    /// 1-based line of the `test` keyword.
+    pub line: usize,
+    /// 0-based line of the opening brace, script positions are relative to it.
+    pub offset: usize,
+}
+
+/// A `#[subscribe("channel")]` handler, run in the background for every message.
+#[derive(Clone, Debug)]
+pub struct Subscription {
+    pub channel: String,
+    pub cfg: HashMap<String, String>,
+    pub route: super::Route,
+    /// 1-based line of the attribute.
+    pub line: usize,
+}
 
 fn extract_cfg(pair: Pair<Rule>) -> HashMap<String, String> {
     let mut cfg = HashMap::new();
@@ -34,6 +53,8 @@ fn extract_block_content(block: &str) -> String {
         .join("\n")
 }
 
+fn line_of(input: &str, offset: usize) -> usize { input[..offset].matches('\n').count() }
+
 fn extract_route_info(pair: Pair<Rule>, input: &str) -> super::Route {
     let mut route_info = super::Route::default();
 
@@ -68,12 +89,8 @@ fn extract_route_info(pair: Pair<Rule>, input: &str) -> super::Route {
                         Rule::block => {
                             route_info.fn_body = extract_block_content(func_pair.as_str()).into();
 
-                            let start_pos = func_pair.as_span().start();
-                            let end_pos = func_pair.as_span().end();
-                            let file_lines: Vec<&str> = input.lines().collect();
-
-                            route_info.start_pos = file_lines.iter().take_while(|line| input.find(line.to_owned()).unwrap() < start_pos).count() - 1;
-                            route_info.end_pos = file_lines.iter().take_while(|line| input.find(line.to_owned()).unwrap() <= end_pos).count() - 1;
+                            route_info.start_pos = line_of(input, func_pair.as_span().start());
+                            route_info.end_pos = line_of(input, func_pair.as_span().end() - 1);
                         }
                         _ => {}
                     }
@@ -82,12 +99,8 @@ fn extract_route_info(pair: Pair<Rule>, input: &str) -> super::Route {
             Rule::block => {
                 route_info.fn_body = extract_block_content(inner_pair.as_str()).into();
 
-                let start_pos = inner_pair.as_span().start();
-                let end_pos = inner_pair.as_span().end();
-                let file_lines: Vec<&str> = input.lines().collect();
-
-                route_info.start_pos = file_lines.iter().take_while(|line| input.find(line.to_owned()).unwrap() < start_pos).count() - 1;
-                route_info.end_pos = file_lines.iter().take_while(|line| input.find(line.to_owned()).unwrap() <= end_pos).count() - 1;
+                route_info.start_pos = line_of(input, inner_pair.as_span().start());
+                route_info.end_pos = line_of(input, inner_pair.as_span().end() - 1);
             }
             _ => {}
         }
@@ -96,31 +109,90 @@ fn extract_route_info(pair: Pair<Rule>, input: &str) -> super::Route {
     route_info
 }
 
-fn process_pair<'i>(pair: Pair<'i, Rule>, input: &'i str) -> Pin<Box<dyn Future<Output = Vec<(String, super::Route)>> + 'i>> {
-    Box::pin(async move {
-        let mut index: Vec<(String, super::Route)> = Vec::new();
-
-        match pair.as_rule() {
-            Rule::route_definition => index.push(extract_route_info(pair, input).save(super::RtKind::Normal).await),
-            Rule::not_found => index.push(extract_route_info(pair, input).save(super::RtKind::NotFound).await),
-            Rule::wildcard => index.push(extract_route_info(pair, input).save(super::RtKind::Wildcard).await),
-            _ => {
-                for inner_pair in pair.into_inner() {
-                    let mut inner_index = process_pair(inner_pair, input).await;
-                    index.append(&mut inner_index);
+fn collect_routes(pair: Pair<Rule>, input: &str, routes: &mut Vec<(super::RtKind, super::Route)>) {
+    match pair.as_rule() {
+        Rule::route_definition => routes.push((super::RtKind::Normal, extract_route_info(pair, input))),
+        Rule::not_found => routes.push((super::RtKind::NotFound, extract_route_info(pair, input))),
+        Rule::wildcard => routes.push((super::RtKind::Wildcard, extract_route_info(pair, input))),
+        Rule::test_block | Rule::subscription => {}
+        _ => {
+            for inner_pair in pair.into_inner() {
+                collect_routes(inner_pair, input, routes);
+            }
+        }
+    }
+}
+
+/// Parses worker source into routes without touching the route cache.
+pub fn parse(input: &str) -> Result<Vec<(super::RtKind, super::Route)>, Box<Error<Rule>>> {
+    let mut routes = Vec::new();
+
+    for pair in RouteParser::parse(Rule::grammar, input)? {
+        collect_routes(pair, input, &mut routes);
+    }
+
+    Ok(routes)
+}
+
+/// Parses the test blocks of a worker file, routes are skipped.
+pub fn cases(input: &str) -> Result<Vec<Case>, Box<Error<Rule>>> {
+    let mut cases = Vec::new();
+
+    for pair in RouteParser::parse(Rule::grammar, input)?.flat_map(|pair| pair.into_inner()) {
+        if pair.as_rule() != Rule::test_block {
+            continue;
+        }
+
+        let line = line_of(input, pair.as_span().start()) + 1;
+        let mut inner = pair.into_inner();
+
+        if let (Some(name), Some(block)) = (inner.next(), inner.next()) {
+            cases.push(Case {
+                line,
+                name: name.as_str().trim_matches(['"', '`']).to_string(),
+                body: block.as_str().to_string(),
+                offset: line_of(input, block.as_span().start()),
+            });
+        }
+    }
+
+    Ok(cases)
+}
+
+/// Parses the subscription handlers of a worker file, routes are skipped.
+pub fn subscriptions(input: &str) -> Result<Vec<Subscription>, Box<Error<Rule>>> {
+    let mut subscriptions = Vec::new();
+
+    for pair in RouteParser::parse(Rule::grammar, input)?.flat_map(|pair| pair.into_inner()) {
+        if pair.as_rule() != Rule::subscription {
+            continue;
+        }
+
+        let line = line_of(input, pair.as_span().start()) + 1;
+        let (mut channel, mut cfg) = (String::new(), HashMap::new());
+
+        if let Some(attr) = pair.clone().into_inner().next() {
+            for attr_pair in attr.into_inner() {
+                match attr_pair.as_rule() {
+                    Rule::string_literal => channel = attr_pair.as_str().trim_matches(['"', '`']).to_string(),
+                    Rule::cfg_block => cfg = extract_cfg(attr_pair),
+                    _ => {}
                 }
             }
         }
 
-        index
-    })
+        let mut route = extract_route_info(pair, input);
+        route.route = channel.to_owned().into();
+
+        subscriptions.push(Subscription { channel, cfg, route, line });
+    }
+
+    Ok(subscriptions)
 }
 
 pub async fn try_parse(input: &str) -> Result<(), Error<Rule>> {
-    let pairs = RouteParser::parse(Rule::grammar, input)?;
-    let futures: Vec<_> = pairs.into_iter().map(|pair| process_pair(pair, input)).collect();
-    let results = join_all(futures).await;
-    let index: Vec<(String, super::Route)> = results.into_iter().flatten().collect();
+    let futures: Vec<_> = parse(input).map_err(|err| *err)?.into_iter().map(|(kind, mut route)| async move { route.save(kind).await }).collect();
+    let index: Vec<(String, super::Route)> = join_all(futures).await;
 
     super::Route::update_index(index).await;
 
@@ -129,5 +201,11 @@ pub async fn try_parse(input: &str) -> Result<(), Error<Rule>> {
         Err(err) => log::error!(err = err.to_string(), "Error during cache cleanup"),
     };
 
+    let valid = super::ROUTES_INDEX.lock().await.iter().map(|item| item.value().inner.cache.to_owned()).collect::<Vec<_>>();
+
+    if let Err(err) = super::cache::persist(valid.into_iter()).await {
+        log::error!(err = err.to_string(), "Error writing cache manifest");
+    };
+
     Ok(())
 }
@@ -1,11 +1,19 @@
 use serde::{Deserialize, Serialize};
-use std::{collections::BTreeMap, path::PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
 use toml::Value;
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Config {
     #[serde(skip)]
     pub config_path: PathBuf,
+    #[serde(skip)]
+    pub profile: Option<String>,
+    /// Paths of the strings `${VAR}` expanded, hidden by `script config show`.
+    #[serde(skip)]
+    pub interpolated: BTreeSet<String>,
     pub workers: Vec<PathBuf>,
     pub settings: Settings,
     pub database: Option<Database>,
@@ -17,13 +25,35 @@ pub struct Settings {
     pub cache: String,
     pub address: String,
     pub port: u16,
+    #[serde(default)]
+    pub cache_format: CacheFormat,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheFormat {
+    #[default]
+    Ron,
+    #[serde(alias = "bin")]
+    Binary,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Database {
     pub kv: Option<KVConfig>,
-    pub mongo: Option<MongoConfig>,
-    pub redis: Option<RedisConfig>,
+    pub mongo: Option<Connections<MongoConfig>>,
+    pub redis: Option<Connections<RedisConfig>>,
+    pub sqlite: Option<Connections<SqliteConfig>>,
+    pub migrations: Option<MigrationsConfig>,
+}
+
+/// The unnamed connection of a section, plus named ones such as `[database.redis.sessions]`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Connections<T> {
+    #[serde(flatten)]
+    pub default: Option<T>,
+    #[serde(flatten)]
+    pub named: BTreeMap<String, T>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -34,12 +64,41 @@ pub struct KVConfig {
 #[derive(Clone, Serialize, Deserialize)]
 pub struct RedisConfig {
     pub server: String,
+    /// Most connections kept open at once, defaults to 10.
+    pub max_pool_size: Option<u32>,
+    /// Idle connections kept ready, defaults to 0.
+    pub min_pool_size: Option<u32>,
+    pub connect_timeout_ms: Option<u64>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SqliteConfig {
+    /// Database file, created when missing.
+    pub path: PathBuf,
+    /// Most connections kept open at once, defaults to 10.
+    pub max_pool_size: Option<u32>,
+    /// How long a write waits for a locked database, defaults to 5000.
+    pub busy_timeout_ms: Option<u64>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct MigrationsConfig {
+    /// Holds `0001_name.up.sql` with `0001_name.down.sql`, or `0001_name.rt`, defaults to migrations.
+    pub dir: Option<PathBuf>,
+    /// Named sqlite connection to migrate, defaults to the unnamed one.
+    pub connection: Option<String>,
+    /// Apply pending migrations before the server starts.
+    #[serde(default)]
+    pub on_start: bool,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct MongoConfig {
     pub server: Option<String>,
     pub advanced: Option<MongoAdvanced>,
+    /// Most connections per server, defaults to 10.
+    pub max_pool_size: Option<u32>,
+    pub min_pool_size: Option<u32>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -47,10 +106,30 @@ pub struct MongoAdvanced {
     pub port: u64,
     pub address: String,
     pub auth: Option<MongoAuth>,
+    pub tls: Option<MongoTls>,
+    pub app_name: Option<String>,
+    pub connect_timeout_ms: Option<u64>,
+    pub server_selection_timeout_ms: Option<u64>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct MongoAuth {
     pub username: String,
     pub password: String,
+    /// Database the user is defined in, defaults to admin.
+    pub source: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MongoTls {
+    pub ca_file: Option<PathBuf>,
+    pub cert_key_file: Option<PathBuf>,
+    #[serde(default)]
+    pub allow_invalid_certificates: bool,
+}
+
+/// An invalid config value, `key` is its TOML path such as `database.redis.server`.
+pub struct Problem {
+    pub key: String,
+    pub message: String,
 }
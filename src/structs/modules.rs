@@ -1,7 +1,11 @@
-use mongodb::sync::{Client as MongoClient, Database};
+use mongodb::{
+    bson::Document,
+    sync::{Client as MongoClient, Cursor, Database},
+};
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
 use redis::Client as RedisClient;
-use rhai::Dynamic;
-use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
 
 #[derive(Clone)]
 pub struct Http {
@@ -13,7 +17,13 @@ pub struct Http {
 
 #[derive(Clone)]
 pub struct Redis {
-    pub client: Option<RedisClient>,
+    pub pool: Pool<RedisClient>,
+}
+
+#[derive(Clone)]
+pub struct RedisPipeline {
+    pub pool: Pool<RedisClient>,
+    pub pipe: redis::Pipeline,
 }
 
 #[derive(Clone)]
@@ -26,8 +36,19 @@ pub struct Mongo {
     pub db: Option<Database>,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct MongoDynamic(pub Dynamic);
+/// Documents are pulled from the server as the script reads them.
+#[derive(Clone)]
+pub struct MongoCursor {
+    pub cursor: Arc<Mutex<Cursor<Document>>>,
+}
+
+#[derive(Clone)]
+pub struct Sql {
+    pub pool: Pool<SqliteConnectionManager>,
+}
 
-unsafe impl Send for MongoDynamic {}
-unsafe impl Sync for MongoDynamic {}
+/// Holds its connection until the transaction commits or rolls back, then `None`.
+#[derive(Clone)]
+pub struct SqlTransaction {
+    pub conn: Arc<Mutex<Option<PooledConnection<SqliteConnectionManager>>>>,
+}
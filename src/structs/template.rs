@@ -15,3 +15,38 @@ pub struct Message<'a> {
     pub error: &'a str,
     pub message: String,
 }
+
+#[derive(Template)]
+#[template(path = "project/config.toml", escape = "none")]
+pub struct ProjectConfig<'a> {
+    pub name: &'a str,
+    pub workers: Vec<&'a str>,
+}
+
+#[derive(Template)]
+#[template(path = "project/api/app.rt", escape = "none")]
+pub struct ApiApp<'a> {
+    pub name: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "project/api/tests.rt", escape = "none")]
+pub struct ApiTests<'a> {
+    pub name: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "project/site/app.rt", escape = "none")]
+pub struct SiteApp;
+
+#[derive(Template)]
+#[template(path = "project/site/tests.rt", escape = "none")]
+pub struct SiteTests<'a> {
+    pub name: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "project/site/index.html")]
+pub struct SiteIndex<'a> {
+    pub name: &'a str,
+}